@@ -148,6 +148,14 @@ impl Expression {
             Rule::Mixer => Ok(Expression::Mixer),
             Rule::Properties => Expression::from_properties(pair),
             Rule::Array => Expression::from_array(pair),
+            // a pattern without methods and properties in a getter
+            Rule::Pattern => Ok(Expression::PatternSuperExpression(
+                PatternSuperExpression::Expression(PatternExpression {
+                    pattern: pair.try_into()?,
+                    methods: Vec::new(),
+                    properties: None,
+                }),
+            )),
             _ => CollyParser::rule_error(&pair),
         }
     }
@@ -265,7 +273,7 @@ impl<'a> TryFrom<Pair<'a, Rule>> for FunctionCall {
 
 //
 #[derive(Debug, Clone, PartialEq)]
-pub struct Properties(HashMap<Identifier, PropertyValue>);
+pub struct Properties(pub HashMap<Identifier, PropertyValue>);
 
 impl<'a> TryFrom<Pair<'a, Rule>> for Properties {
     type Error = Error<Rule>;
@@ -437,9 +445,20 @@ impl<'a> TryFrom<Pair<'a, Rule>> for PatternSuperExpression {
 }
 
 impl PatternSuperExpression {
+    /// Check if any of the patterns has `{expression}` inputs.
+    pub fn has_inputs(&self) -> bool {
+        match self {
+            PatternSuperExpression::ExpressionList(expressions) => expressions
+                .iter()
+                .any(|expression| expression.pattern.has_inputs()),
+            PatternSuperExpression::Expression(expression) => {
+                expression.pattern.has_inputs()
+            }
+        }
+    }
+
     fn from_pattern_expression(pair: Pair<'_, Rule>) -> ParseResult<Self> {
-        let inner = CollyParser::first_inner_for_pair(pair)?;
-        Ok(PatternSuperExpression::Expression(inner.try_into()?))
+        Ok(PatternSuperExpression::Expression(pair.try_into()?))
     }
 
     fn from_pattern_expression_list(pair: Pair<'_, Rule>) -> ParseResult<Self> {
@@ -513,10 +532,30 @@ impl<'a> TryFrom<Pair<'a, Rule>> for Pattern {
     }
 }
 
+impl Pattern {
+    pub fn has_inputs(&self) -> bool {
+        self.0.iter().any(BeatEvent::has_inputs)
+    }
+}
+
 //
 #[derive(Debug, Clone, PartialEq)]
 pub struct BeatEvent(pub Vec<Event>);
 
+impl BeatEvent {
+    pub fn has_inputs(&self) -> bool {
+        self.0.iter().any(|event| match event {
+            Event::Group(atoms) => atoms.iter().any(|atom| {
+                matches!(atom.value, PatternAtomValue::PatternInput(_))
+            }),
+            Event::Chord(chord) => chord.inner.iter().any(Self::has_inputs),
+            Event::ParenthesisedEvent(event) => {
+                event.inner.iter().any(Self::has_inputs)
+            }
+        })
+    }
+}
+
 impl<'a> TryFrom<Pair<'a, Rule>> for BeatEvent {
     type Error = Error<Rule>;
 
@@ -725,6 +764,8 @@ pub enum EventMethod {
     Dot,
     Multiply,
     Divide,
+    Accent,
+    Ghost,
 }
 
 impl<'a> TryFrom<Pair<'a, Rule>> for EventMethod {
@@ -735,6 +776,8 @@ impl<'a> TryFrom<Pair<'a, Rule>> for EventMethod {
             "." => Ok(EventMethod::Dot),
             "*" => Ok(EventMethod::Multiply),
             ":" => Ok(EventMethod::Divide),
+            "!" => Ok(EventMethod::Accent),
+            "?" => Ok(EventMethod::Ghost),
             _ => CollyParser::rule_error(&pair),
         }
     }
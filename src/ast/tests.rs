@@ -82,6 +82,16 @@ fn test_parse_property_getter() {
         Rule::Expression,
     );
     assert_eq!(expected, result.unwrap());
+
+    let result: ParseResult<Expression> =
+        CollyParser::parse_source_for_rule("| 0 1 |.length", Rule::Expression);
+    match result.unwrap() {
+        Expression::PropertyGetter { assignee, .. } => match *assignee {
+            Expression::PatternSuperExpression(_) => (),
+            assignee => panic!("unexpected assignee {:?}", assignee),
+        },
+        expression => panic!("unexpected expression {:?}", expression),
+    }
 }
 
 #[test]
@@ -546,6 +556,23 @@ fn parse_atom_with_methods() {
     };
 
     assert_eq!(expected, result.unwrap());
+
+    let result: ParseResult<PatternAtom> =
+        CollyParser::parse_source_for_rule("3!!?.", Rule::PatternAtom);
+    let expected = PatternAtom {
+        value: PatternAtomValue::Note(Note {
+            pitch: 3,
+            alteration: Vec::new(),
+        }),
+        methods: vec![
+            EventMethod::Accent,
+            EventMethod::Accent,
+            EventMethod::Ghost,
+            EventMethod::Dot,
+        ],
+    };
+
+    assert_eq!(expected, result.unwrap());
 }
 
 #[test]
@@ -740,6 +767,27 @@ fn test_pattern_assignment() {
     assert_eq!(expected, result.unwrap());
 }
 
+#[test]
+fn test_pattern_assignment_with_events() {
+    let result: ParseResult<Assignment> = CollyParser::parse_source_for_rule(
+        "$1@0 | 0 1 |",
+        Rule::AssignStatement,
+    );
+    let expected = Assignment::Pattern {
+        assignee: Expression::PatternSlot((1, 0)),
+        assignment: PatternSuperExpression::Expression(PatternExpression {
+            pattern: CollyParser::parse_source_for_rule(
+                "| 0 1 |",
+                Rule::Pattern,
+            )
+            .unwrap(),
+            methods: Vec::new(),
+            properties: None,
+        }),
+    };
+    assert_eq!(expected, result.unwrap());
+}
+
 #[should_panic]
 #[test]
 fn test_parse_event_as_event_method() {
@@ -1,5 +1,131 @@
+use colly::ast::Ast;
+use colly::clock::{Bpm, Clock};
+use colly::interpreter::{Context, Interpreter};
+use colly::live::Session;
+use colly::midi;
+use colly::output::{Logger, MidiFileWriter, Osc};
+use colly::player::Player;
+use colly::repl::Repl;
+use colly::settings::Settings;
+use colly::types::{Mixer, Tuning};
 use human_panic::setup_panic;
+use std::env;
+use std::fs;
+use std::io;
+use std::process;
+use std::str::FromStr;
 
-fn main() {
+const USAGE: &str = "Usage:
+    colly                          start the REPL
+    colly run FILE                 play FILE reloading it on changes
+    colly export FILE BEATS MIDI   write BEATS beats of FILE to MIDI file";
+const SETTINGS_FILE: &str = "settings";
+
+// human-panic 1.0 still refers to `std::panic::PanicInfo`
+#[allow(deprecated)]
+fn main() -> io::Result<()> {
     setup_panic!();
+
+    let args: Vec<String> = env::args().skip(1).collect();
+    match args.iter().map(String::as_str).collect::<Vec<&str>>()[..] {
+        // the events are logged apart from the prompt and the results
+        [] => Repl::with_session(session(Logger::new(io::stderr()))?)
+            .run(io::BufReader::new(io::stdin()), &mut io::stdout()),
+        ["run", path] => {
+            session(Logger::stdout())?.run_file(path, &mut io::stderr())
+        }
+        ["export", path, beats, output] => match beats.parse() {
+            Ok(beats) => export(path, beats, output),
+            Err(_) => usage(),
+        },
+        _ => usage(),
+    }
+}
+
+fn usage() -> ! {
+    eprintln!("{}", USAGE);
+    process::exit(2);
+}
+
+// the session playing to the outputs from the settings, the tracks
+// without routing are logged by the `logger`
+fn session<'a, W>(logger: Logger<W>) -> io::Result<Session<'a>>
+where
+    W: io::Write + Send + 'static,
+{
+    let settings = settings();
+    let tempo = tempo(&settings);
+    let clock = Clock::new(tempo, &settings.clock);
+    let mut mixer = Mixer::default();
+    mixer.clock = clock.clone();
+
+    let player = Player::new(clock, Box::new(logger));
+    player.add_output("osc", Box::new(Osc::new(&settings.osc)?));
+    player.add_output(
+        "midi_file",
+        Box::new(MidiFileWriter::new(
+            &settings.midi_file.path,
+            tempo,
+            settings.clock.resolution,
+        )?),
+    );
+
+    let mut session = Session::new(mixer, player);
+    add_definitions(session.context_mut(), settings);
+    Ok(session)
+}
+
+fn export(path: &str, beats: u64, output: &str) -> io::Result<()> {
+    let settings = settings();
+    let mut context = Context::default();
+    context.mixer_mut().clock = Clock::new(tempo(&settings), &settings.clock);
+    add_definitions(&mut context, settings);
+
+    let source = fs::read_to_string(path)?;
+    let result = Ast::from_str(&source)
+        .map_err(|error| error.to_string())
+        .and_then(|ast| {
+            ast.interpret(&mut context)
+                .map_err(|error| error.to_string())
+        });
+    if let Err(error) = result {
+        eprintln!("Error: {}", error);
+        process::exit(1);
+    }
+
+    midi::export_to_file(context.mixer(), beats, output)
+}
+
+fn settings() -> Settings {
+    Settings::new(config::File::with_name(SETTINGS_FILE).required(false))
+        .unwrap_or_else(|error| {
+            eprintln!("Error reading settings: {}", error);
+            process::exit(1);
+        })
+}
+
+fn tempo(settings: &Settings) -> Bpm {
+    Bpm::new(settings.clock.tempo).unwrap_or_else(|error| {
+        eprintln!("Error reading settings: {}", error);
+        process::exit(1);
+    })
+}
+
+// the scales, the grooves and the tunings from the settings
+fn add_definitions(context: &mut Context, settings: Settings) {
+    context.add_scales(settings.scales);
+    context.add_grooves(settings.grooves);
+    context.add_tunings(
+        settings
+            .tunings
+            .iter()
+            .map(|(name, path)| {
+                let tuning = Tuning::load(path).unwrap_or_else(|error| {
+                    eprintln!("Error loading tuning {}: {}", name, error);
+                    process::exit(1);
+                });
+                (name.clone(), tuning)
+            })
+            .collect(),
+    );
 }
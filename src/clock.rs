@@ -1,41 +1,165 @@
 use crate::settings;
+use crate::types::Curve;
+use serde::Deserialize;
 use std::cmp::{Ord, Ordering};
+use std::convert::TryFrom;
+use std::fmt;
+use std::str::FromStr;
 use std::time;
 
 pub type Resolution = u64;
 
 #[derive(Debug, Clone)]
 pub struct Clock {
-    tempo: Bpm,
+    tempo_map: TempoMap,
+    min_tempo: f64,
+    max_tempo: f64,
+    tap_tempo: TapTempo,
     cursor: Cursor,
-    tick_interval: time::Duration,
     resolution: Resolution,
+    beats_per_bar: u64,
+    quantisation: Quantisation,
 }
 
 impl Clock {
+    /// The `tempo` is clamped to the limits of the `options`.
     pub fn new(tempo: Bpm, options: &settings::Clock) -> Self {
+        let min_tempo = options.min_tempo.max(f64::MIN_POSITIVE);
+        let max_tempo = options.max_tempo.max(min_tempo);
         Clock {
-            tempo,
+            tempo_map: TempoMap::new(Bpm(tempo.0.clamp(min_tempo, max_tempo))),
+            min_tempo,
+            max_tempo,
+            tap_tempo: TapTempo::new(options.tap_tempo_taps),
             cursor: Cursor::new(options.resolution),
-            tick_interval: time::Duration::from_secs_f64(1.0 / options.resolution as f64),
             resolution: options.resolution,
+            beats_per_bar: options.beats_per_bar.max(1),
+            quantisation: options.quantisation,
         }
     }
 
-    pub fn set_tempo(&mut self, tempo: Bpm) {
-        self.tempo = tempo;
+    /// Set the tempo from the cursor's position on. Returns a warning if
+    /// the tempo is out of the limits and has been clamped.
+    pub fn set_tempo(&mut self, tempo: Bpm) -> Option<ClockWarning> {
+        let (tempo, warning) = self.clamp(tempo);
+        self.tempo_map.set_tempo(self.cursor.position, tempo);
+        warning
     }
 
+    /// Change the tempo to `tempo` during `length` from the cursor's
+    /// position. The tempo is clamped as in
+    /// [set_tempo](#method.set_tempo).
+    pub fn ramp_tempo(
+        &mut self,
+        tempo: Bpm,
+        length: Duration,
+        curve: Curve,
+    ) -> Option<ClockWarning> {
+        let (tempo, warning) = self.clamp(tempo);
+        self.tempo_map
+            .ramp(self.cursor.position, tempo, length, curve);
+        warning
+    }
+
+    /// Register a tap at the time `at`. The tempo is set to the average
+    /// of the intervals between the last taps.
+    pub fn tap(&mut self, at: time::Instant) -> Option<ClockWarning> {
+        // the taps, which are further apart than the beats of the
+        // slowest tempo, start over
+        let timeout = time::Duration::from_secs_f64(60.0 / self.min_tempo);
+        match self.tap_tempo.tap(at, timeout) {
+            Some(tempo) => self.set_tempo(tempo),
+            None => None,
+        }
+    }
+
+    /// The lowest and the highest tempos.
+    pub fn tempo_limits(&self) -> (Bpm, Bpm) {
+        (Bpm(self.min_tempo), Bpm(self.max_tempo))
+    }
+
+    fn clamp(&self, tempo: Bpm) -> (Bpm, Option<ClockWarning>) {
+        let clamped = tempo.0.clamp(self.min_tempo, self.max_tempo);
+        if clamped == tempo.0 {
+            (tempo, None)
+        } else {
+            (
+                Bpm(clamped),
+                Some(ClockWarning::TempoClamped {
+                    tempo: tempo.0,
+                    clamped,
+                }),
+            )
+        }
+    }
+
+    /// The tempo at the cursor's position.
     pub fn tempo(&self) -> Bpm {
-        self.tempo
+        self.tempo_map.tempo(self.cursor.position)
+    }
+
+    pub fn tempo_map(&self) -> &TempoMap {
+        &self.tempo_map
+    }
+
+    /// Replace the tempo map from the cursor's position on with `map`,
+    /// which positions are relative to the cursor's one.
+    pub fn splice_tempo_map(&mut self, map: &TempoMap) {
+        self.tempo_map.splice(self.cursor.position, map);
+    }
+
+    /// The time from the zero position to `position`.
+    pub fn seconds(&self, position: CursorPosition) -> f64 {
+        self.tempo_map.seconds(position)
+    }
+
+    /// The position of the tick, which starts at `seconds` or right
+    /// before.
+    pub fn position(&self, seconds: f64) -> CursorPosition {
+        self.tempo_map.position(seconds, self.resolution)
     }
 
     pub fn cursor(&self) -> &Cursor {
         &self.cursor
     }
 
+    pub fn cursor_mut(&mut self) -> &mut Cursor {
+        &mut self.cursor
+    }
+
+    /// The duration of the tick at the cursor's position.
     pub fn tick_interval(&self) -> time::Duration {
-        self.tick_interval
+        let position = self.cursor.position;
+        time::Duration::from_secs_f64(
+            self.seconds(position + 1) - self.seconds(position),
+        )
+    }
+
+    pub fn resolution(&self) -> Resolution {
+        self.resolution
+    }
+
+    /// The time signature as the number of beats in a bar.
+    pub fn beats_per_bar(&self) -> u64 {
+        self.beats_per_bar
+    }
+
+    pub fn set_beats_per_bar(&mut self, beats: u64) {
+        self.beats_per_bar = beats.max(1);
+    }
+
+    pub fn quantisation(&self) -> Quantisation {
+        self.quantisation
+    }
+
+    pub fn set_quantisation(&mut self, quantisation: Quantisation) {
+        self.quantisation = quantisation;
+    }
+
+    /// The position, at which a change made at `position` should take
+    /// effect according to the quantisation.
+    pub fn quantise(&self, position: CursorPosition) -> CursorPosition {
+        self.quantisation.apply(position, self.beats_per_bar)
     }
 }
 
@@ -162,6 +286,11 @@ impl CursorPosition {
     pub fn resolution(&self) -> Resolution {
         self.resolution
     }
+
+    /// The number of the bar of the position.
+    pub fn bar(&self, beats_per_bar: u64) -> u64 {
+        self.beat / beats_per_bar.max(1)
+    }
 }
 
 impl std::ops::Add for CursorPosition {
@@ -318,18 +447,380 @@ impl PartialOrd for CursorPosition {
 
 pub type Duration = CursorPosition;
 
-#[derive(Debug, Clone, Copy)]
+/// The grid, which changes of patterns are aligned to, so live edits land
+/// in time. It's written as `tick`, `beat`, `bar` or `<n> bars`.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Deserialize)]
+#[serde(try_from = "String")]
+pub enum Quantisation {
+    Tick,
+    #[default]
+    Beat,
+    Bar,
+    Bars(u64),
+}
+
+impl Quantisation {
+    /// The first position on the grid at or after `position`. Bars are
+    /// counted from the zero position.
+    pub fn apply(
+        &self,
+        position: CursorPosition,
+        beats_per_bar: u64,
+    ) -> CursorPosition {
+        let resolution = position.resolution();
+        let step = match self {
+            Quantisation::Tick => 1,
+            Quantisation::Beat => resolution,
+            Quantisation::Bar => beats_per_bar * resolution,
+            Quantisation::Bars(bars) => bars * beats_per_bar * resolution,
+        }
+        .max(1);
+
+        let ticks = position.as_ticks();
+        CursorPosition::from_ticks(ticks.div_ceil(step) * step, resolution)
+    }
+}
+
+impl FromStr for Quantisation {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let error = || format!("invalid quantisation {:?}", value);
+        match value.trim() {
+            "tick" => Ok(Quantisation::Tick),
+            "beat" => Ok(Quantisation::Beat),
+            "bar" => Ok(Quantisation::Bar),
+            value => {
+                let mut words = value.split_whitespace();
+                match (words.next(), words.next(), words.next()) {
+                    (Some(bars), Some("bars"), None)
+                    | (Some(bars), Some("bar"), None) => bars
+                        .parse()
+                        .ok()
+                        .filter(|bars| *bars > 0)
+                        .map(Quantisation::Bars)
+                        .ok_or_else(error),
+                    _ => Err(error()),
+                }
+            }
+        }
+    }
+}
+
+impl TryFrom<String> for Quantisation {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl fmt::Display for Quantisation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Quantisation::Tick => write!(f, "tick"),
+            Quantisation::Beat => write!(f, "beat"),
+            Quantisation::Bar => write!(f, "bar"),
+            Quantisation::Bars(bars) => write!(f, "{} bars", bars),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct Bpm(f64);
 
 impl Bpm {
-    pub fn set(&mut self, value: f64) {
-        self.0 = value.min(200.0).max(27.0);
+    /// The tempo should be a positive number. The limits of the tempo are
+    /// applied by the [Clock](struct.Clock.html).
+    pub fn new(value: f64) -> Result<Self, ClockError> {
+        if value.is_finite() && value > 0.0 {
+            Ok(Bpm(value))
+        } else {
+            Err(ClockError::InvalidTempo(value))
+        }
+    }
+
+    pub fn get(&self) -> f64 {
+        self.0
     }
 }
 
 impl Default for Bpm {
     fn default() -> Self {
-        Bpm(117.0)
+        Bpm(settings::Clock::default().tempo)
+    }
+}
+
+/// Averages the intervals between the last taps.
+#[derive(Debug, Clone)]
+struct TapTempo {
+    taps: Vec<time::Instant>,
+    // the number of the intervals averaged
+    count: usize,
+}
+
+impl TapTempo {
+    fn new(count: usize) -> Self {
+        TapTempo {
+            taps: Vec::new(),
+            count: count.max(1),
+        }
+    }
+
+    // The tempo, if there are at least two taps in a row, which are
+    // closer than `timeout`.
+    fn tap(
+        &mut self,
+        at: time::Instant,
+        timeout: time::Duration,
+    ) -> Option<Bpm> {
+        if let Some(last) = self.taps.last() {
+            if at <= *last || at - *last > timeout {
+                self.taps.clear();
+            }
+        }
+        self.taps.push(at);
+        if self.taps.len() > self.count + 1 {
+            self.taps.remove(0);
+        }
+
+        let first = self.taps.first()?;
+        let intervals = self.taps.len() as u32 - 1;
+        if intervals == 0 {
+            return None;
+        }
+        let interval = (at - *first) / intervals;
+        Bpm::new(60.0 / interval.as_secs_f64()).ok()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ClockWarning {
+    TempoClamped { tempo: f64, clamped: f64 },
+}
+
+impl fmt::Display for ClockWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClockWarning::TempoClamped { tempo, clamped } => write!(
+                f,
+                "Tempo {} is out of the limits, {} is used instead",
+                tempo, clamped
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Fail, PartialEq)]
+pub enum ClockError {
+    #[fail(display = "Invalid tempo {}, it should be a positive number", 0)]
+    InvalidTempo(f64),
+}
+
+/// A segment of a [TempoMap](struct.TempoMap.html). The tempo starts at
+/// `position` with `tempo` and either holds until the next segment, or
+/// changes to the tempo of the next segment along the `curve`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TempoSegment {
+    pub position: CursorPosition,
+    pub tempo: Bpm,
+    pub curve: Option<Curve>,
+}
+
+/// The tempo changes over time. The positions of the segments are
+/// absolute, so the map converts positions to seconds and back.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TempoMap {
+    // sorted by the positions, the first one is always at the zero
+    // position
+    segments: Vec<TempoSegment>,
+}
+
+impl TempoMap {
+    /// A map with the constant `tempo`.
+    pub fn new(tempo: Bpm) -> Self {
+        TempoMap {
+            segments: vec![TempoSegment {
+                position: CursorPosition::default(),
+                tempo,
+                curve: None,
+            }],
+        }
+    }
+
+    pub fn segments(&self) -> &[TempoSegment] {
+        &self.segments
+    }
+
+    pub fn tempo(&self, position: CursorPosition) -> Bpm {
+        let index = self.index(position.as_f64());
+        let segment = &self.segments[index];
+        match (segment.curve, self.segments.get(index + 1)) {
+            (Some(curve), Some(next)) => {
+                let start = segment.position.as_f64();
+                let progress = (position.as_f64() - start)
+                    / (next.position.as_f64() - start);
+                Bpm(curve.interpolate(segment.tempo.0, next.tempo.0, progress))
+            }
+            _ => segment.tempo,
+        }
+    }
+
+    /// Hold `tempo` from `position` on. The later segments are removed.
+    pub fn set_tempo(&mut self, position: CursorPosition, tempo: Bpm) {
+        self.splice(position, &TempoMap::new(tempo));
+    }
+
+    /// Change the tempo from the one at `position` to `tempo` during
+    /// `length` and hold it then. The later segments are removed.
+    pub fn ramp(
+        &mut self,
+        position: CursorPosition,
+        tempo: Bpm,
+        length: Duration,
+        curve: Curve,
+    ) {
+        let start = CursorPosition::new(position.resolution());
+        let map = TempoMap {
+            segments: vec![
+                TempoSegment {
+                    position: start,
+                    tempo: self.tempo(position),
+                    curve: Some(curve),
+                },
+                TempoSegment {
+                    position: start + length,
+                    tempo,
+                    curve: None,
+                },
+            ],
+        };
+        self.splice(position, &map);
+    }
+
+    /// Replace the segments from `position` on with the ones of `map`
+    /// moved by `position`.
+    pub fn splice(&mut self, position: CursorPosition, map: &TempoMap) {
+        let tempo = self.tempo(position);
+        self.segments
+            .retain(|segment| segment.position.as_f64() < position.as_f64());
+        // a ramp, which is cut, ends at the position with the tempo it
+        // has there
+        if let Some(last) = self.segments.last_mut() {
+            if last.curve.is_some() {
+                self.segments.push(TempoSegment {
+                    position,
+                    tempo,
+                    curve: None,
+                });
+            }
+        }
+        // the resolutions of the maps can differ
+        let resolution = position.resolution() as f64;
+        self.segments
+            .extend(map.segments.iter().map(|segment| TempoSegment {
+                position: position
+                    + (segment.position.as_f64() * resolution).round() as u64,
+                ..*segment
+            }));
+    }
+
+    /// The time from the zero position to `position`.
+    pub fn seconds(&self, position: CursorPosition) -> f64 {
+        let beats = position.as_f64();
+        let index = self.index(beats);
+        let elapsed: f64 =
+            (0..index).map(|n| self.segment_seconds(n, None)).sum();
+        let offset = beats - self.segments[index].position.as_f64();
+        elapsed + self.segment_seconds(index, Some(offset))
+    }
+
+    /// The position of the tick, which starts at `seconds` or right
+    /// before.
+    pub fn position(
+        &self,
+        seconds: f64,
+        resolution: Resolution,
+    ) -> CursorPosition {
+        let mut seconds = seconds.max(0.0);
+        let mut index = 0;
+        while index + 1 < self.segments.len() {
+            let duration = self.segment_seconds(index, None);
+            if seconds < duration {
+                break;
+            }
+            seconds -= duration;
+            index += 1;
+        }
+
+        let (start, length, from, to, curve) = self.shape(index);
+        let beats = match curve {
+            Some(curve) if from != to => match curve {
+                Curve::Linear => {
+                    let slope = (to - from) / length;
+                    (from * (seconds * slope / 60.0).exp() - from) / slope
+                }
+                Curve::Exponential => {
+                    let ratio = (to / from).ln();
+                    -length
+                        * (1.0 - seconds * from * ratio / 60.0 / length).ln()
+                        / ratio
+                }
+            },
+            _ => seconds * from / 60.0,
+        };
+        let ticks = (start + beats) * resolution as f64;
+        // the rounding errors shouldn't move the position to the tick
+        // before
+        CursorPosition::from_ticks((ticks + 1e-6).floor() as u64, resolution)
+    }
+
+    // The index of the segment, which contains the position in beats.
+    fn index(&self, beats: f64) -> usize {
+        self.segments
+            .iter()
+            .rposition(|segment| segment.position.as_f64() <= beats)
+            .unwrap_or(0)
+    }
+
+    // The start and the length in beats, the tempos at the start and at
+    // the end and the curve of the segment with the `index`. The length
+    // of the last segment is infinite.
+    fn shape(&self, index: usize) -> (f64, f64, f64, f64, Option<Curve>) {
+        let segment = &self.segments[index];
+        let start = segment.position.as_f64();
+        match self.segments.get(index + 1) {
+            Some(next) => (
+                start,
+                next.position.as_f64() - start,
+                segment.tempo.0,
+                next.tempo.0,
+                segment.curve,
+            ),
+            None => {
+                (start, f64::INFINITY, segment.tempo.0, segment.tempo.0, None)
+            }
+        }
+    }
+
+    // The time of the first `beats` of the segment, or of the whole one.
+    fn segment_seconds(&self, index: usize, beats: Option<f64>) -> f64 {
+        let (_, length, from, to, curve) = self.shape(index);
+        let beats = beats.unwrap_or(length);
+        match curve {
+            // ramps of zero length are jumps
+            Some(_) if from == to || length == 0.0 => 60.0 * beats / from,
+            Some(Curve::Linear) => {
+                let slope = (to - from) / length;
+                60.0 / slope * ((from + slope * beats) / from).ln()
+            }
+            Some(Curve::Exponential) => {
+                let ratio = (to / from).ln();
+                60.0 * length / from / ratio
+                    * (1.0 - (-ratio * beats / length).exp())
+            }
+            None => 60.0 * beats / from,
+        }
     }
 }
 
@@ -338,6 +829,165 @@ mod tests {
     use super::*;
     use approx::assert_relative_eq;
 
+    #[test]
+    fn tick_interval() {
+        let mut clock = Clock::new(
+            Bpm::new(120.0).unwrap(),
+            &settings::Clock {
+                resolution: 4,
+                ..Default::default()
+            },
+        );
+        assert_eq!(time::Duration::from_millis(125), clock.tick_interval());
+
+        assert_eq!(None, clock.set_tempo(Bpm::new(60.0).unwrap()));
+        assert_eq!(time::Duration::from_millis(250), clock.tick_interval());
+    }
+
+    #[test]
+    fn tempo_limits() {
+        assert!(Bpm::new(-1.0).is_err());
+        assert!(Bpm::new(f64::NAN).is_err());
+        assert!(Bpm::new(0.0).is_err());
+
+        let mut clock = Clock::new(
+            Bpm::new(300.0).unwrap(),
+            &settings::Clock {
+                min_tempo: 20.0,
+                max_tempo: 250.0,
+                ..Default::default()
+            },
+        );
+        assert_relative_eq!(250.0, clock.tempo().get());
+        assert_eq!(None, clock.set_tempo(Bpm::new(20.5).unwrap()));
+        assert_eq!(
+            Some(ClockWarning::TempoClamped {
+                tempo: 10.0,
+                clamped: 20.0
+            }),
+            clock.set_tempo(Bpm::new(10.0).unwrap())
+        );
+        assert_relative_eq!(20.0, clock.tempo().get());
+    }
+
+    #[test]
+    fn tap_tempo() {
+        let mut clock = Clock::new(
+            Bpm::default(),
+            &settings::Clock {
+                tap_tempo_taps: 2,
+                ..Default::default()
+            },
+        );
+        let start = time::Instant::now();
+        let tap = |clock: &mut Clock, millis| {
+            clock.tap(start + time::Duration::from_millis(millis))
+        };
+
+        tap(&mut clock, 0);
+        assert_relative_eq!(117.0, clock.tempo().get());
+        tap(&mut clock, 500);
+        assert_relative_eq!(120.0, clock.tempo().get(), epsilon = 1e-9);
+        tap(&mut clock, 1100);
+        assert_relative_eq!(109.090_909, clock.tempo().get(), epsilon = 1e-6);
+        // only the last two intervals are averaged
+        tap(&mut clock, 1700);
+        assert_relative_eq!(100.0, clock.tempo().get(), epsilon = 1e-9);
+        // the taps start over after a pause
+        tap(&mut clock, 5000);
+        tap(&mut clock, 5400);
+        assert_relative_eq!(150.0, clock.tempo().get(), epsilon = 1e-9);
+    }
+
+    #[test]
+    fn quantise_positions() {
+        let clock = |quantisation| {
+            Clock::new(
+                Bpm::default(),
+                &settings::Clock {
+                    resolution: 4,
+                    beats_per_bar: 3,
+                    quantisation,
+                    ..Default::default()
+                },
+            )
+        };
+        let position = |ticks| CursorPosition::from_ticks(ticks, 4);
+
+        assert_eq!(
+            position(5),
+            clock(Quantisation::Tick).quantise(position(5))
+        );
+        assert_eq!(
+            position(8),
+            clock(Quantisation::Beat).quantise(position(5))
+        );
+        assert_eq!(
+            position(8),
+            clock(Quantisation::Beat).quantise(position(8))
+        );
+        assert_eq!(
+            position(12),
+            clock(Quantisation::Bar).quantise(position(5))
+        );
+        assert_eq!(
+            position(24),
+            clock(Quantisation::Bars(2)).quantise(position(13))
+        );
+        assert_eq!(1, position(13).bar(3));
+
+        assert_eq!(Ok(Quantisation::Bars(4)), "4 bars".parse());
+        assert_eq!(Ok(Quantisation::Bar), "bar".parse());
+        assert!("0 bars".parse::<Quantisation>().is_err());
+        assert!("bars".parse::<Quantisation>().is_err());
+        assert_eq!("2 bars", Quantisation::Bars(2).to_string());
+    }
+
+    #[test]
+    fn tempo_map_ramps() {
+        let resolution = 4;
+        let position = |beat| CursorPosition::from((beat, 0, resolution));
+        let bpm = |value| Bpm::new(value).unwrap();
+
+        let mut map = TempoMap::new(bpm(60.0));
+        map.ramp(position(4), bpm(120.0), position(4), Curve::Linear);
+        assert_relative_eq!(60.0, map.tempo(position(3)).get());
+        assert_relative_eq!(90.0, map.tempo(position(6)).get());
+        assert_relative_eq!(120.0, map.tempo(position(10)).get());
+
+        let ramp = 4.0 * 2f64.ln();
+        assert_relative_eq!(4.0, map.seconds(position(4)));
+        assert_relative_eq!(4.0 + ramp, map.seconds(position(8)));
+        assert_relative_eq!(4.5 + ramp, map.seconds(position(9)));
+        for tick in 0..48 {
+            let position = CursorPosition::from_ticks(tick, resolution);
+            assert_eq!(
+                position,
+                map.position(map.seconds(position), resolution)
+            );
+        }
+
+        // the cut ramp keeps its shape
+        map.set_tempo(position(6), bpm(100.0));
+        assert_eq!(4, map.segments().len());
+        assert_relative_eq!(75.0, map.tempo(position(5)).get());
+        assert_relative_eq!(100.0, map.tempo(position(7)).get());
+
+        let mut map = TempoMap::new(bpm(60.0));
+        map.ramp(position(0), bpm(120.0), position(4), Curve::Exponential);
+        assert_relative_eq!(
+            60.0 * 2f64.sqrt(),
+            map.tempo(position(2)).get(),
+            epsilon = 1e-9
+        );
+        assert_relative_eq!(
+            2.0 / 2f64.ln(),
+            map.seconds(position(4)),
+            epsilon = 1e-9
+        );
+        assert_eq!(position(4), map.position(2.0 / 2f64.ln(), resolution));
+    }
+
     #[test]
     fn position_from_relative() {
         let resolution = 1920;
@@ -3,11 +3,16 @@ mod tests;
 
 use crate::{
     ast,
-    types::{self, Function, Identifier, Mixer, Value},
+    clock::{CursorPosition, Duration},
+    stdlib,
+    types::{self, Function, HasProperties, Identifier, Mixer, TypeId, Value},
 };
 
 use std::{cell::RefCell, collections::HashMap, rc::Rc};
 
+// the default range of modulation values
+const MODULATION_RANGE: (f64, f64) = (0.0, 1.0);
+
 type InterpreterResult<T> = Result<T, InterpreterError>;
 
 pub trait Interpreter<V> {
@@ -19,6 +24,9 @@ pub struct Context<'a> {
     mixer: Mixer,
     variables: VariablesTable,
     functions: HashMap<Identifier, Box<dyn Function<Item = Value>>>,
+    scales: HashMap<String, types::Scale>,
+    tunings: HashMap<String, types::Tuning>,
+    grooves: HashMap<String, types::Groove>,
 }
 
 #[derive(Debug, Default)]
@@ -33,34 +41,469 @@ impl VariablesTable {
     }
 }
 
+impl<'a> Context<'a> {
+    pub fn mixer(&self) -> &Mixer {
+        &self.mixer
+    }
+
+    pub fn mixer_mut(&mut self) -> &mut Mixer {
+        &mut self.mixer
+    }
+
+    /// Add the function to the registry. A function with the same
+    /// identifier is replaced.
+    pub fn register_function(
+        &mut self,
+        function: Box<dyn Function<Item = Value>>,
+    ) {
+        self.functions.insert(function.identifier(), function);
+    }
+
+    /// Look up the function in this context and then in the parents.
+    pub fn function(
+        &self,
+        identifier: &Identifier,
+    ) -> Option<&dyn Function<Item = Value>> {
+        match self.functions.get(identifier) {
+            Some(function) => Some(function.as_ref()),
+            None => self
+                .parent
+                .as_ref()
+                .and_then(|parent| parent.function(identifier)),
+        }
+    }
+
+    /// Add named scales. The scales with the same names are replaced.
+    pub fn add_scales(&mut self, scales: HashMap<String, types::Scale>) {
+        self.scales.extend(types::Scale::with_keys(scales));
+    }
+
+    /// Look up the scale by its name in this context and then in the
+    /// parents.
+    pub fn scale(&self, name: &str) -> Option<&types::Scale> {
+        match self.scales.get(name) {
+            Some(scale) => Some(scale),
+            None => self.parent.as_ref().and_then(|parent| parent.scale(name)),
+        }
+    }
+
+    // `<Nothing>` resets the scale
+    fn scale_value(
+        &self,
+        value: Value,
+    ) -> InterpreterResult<Option<types::Scale>> {
+        match value {
+            Value::String(name) => self
+                .scale(&name)
+                .cloned()
+                .map(Some)
+                .ok_or(InterpreterError::UnknownScale(name)),
+            Value::Nothing => Ok(None),
+            value => Err(InterpreterError::Rule(
+                "scale".into(),
+                format!(
+                    "expected scale name as <String>, found {}",
+                    value.type_id()
+                ),
+            )),
+        }
+    }
+
+    /// Add named tunings. The tunings with the same names are replaced.
+    pub fn add_tunings(&mut self, tunings: HashMap<String, types::Tuning>) {
+        self.tunings.extend(tunings);
+    }
+
+    /// Look up the tuning by its name in this context and then in
+    /// the parents. Equal divisions of the octave like `19edo` are always
+    /// available and the names ending with `.scl` are loaded as Scala
+    /// files.
+    pub fn tuning(&self, name: &str) -> InterpreterResult<types::Tuning> {
+        if let Some(tuning) = self.tunings.get(name) {
+            return Ok(tuning.clone());
+        }
+
+        if let Some(parent) = self.parent.as_ref() {
+            if let Ok(tuning) = parent.tuning(name) {
+                return Ok(tuning);
+            }
+        }
+
+        if name.ends_with(".scl") {
+            return types::Tuning::load(name).map_err(|error| {
+                InterpreterError::Rule("tuning".into(), error.to_string())
+            });
+        }
+
+        types::Tuning::from_name(name)
+            .ok_or_else(|| InterpreterError::UnknownTuning(name.to_string()))
+    }
+
+    // `<Nothing>` resets the tuning
+    fn tuning_value(
+        &self,
+        value: Value,
+    ) -> InterpreterResult<Option<types::Tuning>> {
+        match value {
+            Value::String(name) => self.tuning(&name).map(Some),
+            Value::Nothing => Ok(None),
+            value => Err(InterpreterError::Rule(
+                "tuning".into(),
+                format!(
+                    "expected tuning name as <String>, found {}",
+                    value.type_id()
+                ),
+            )),
+        }
+    }
+
+    /// Add named grooves. The grooves with the same names are replaced.
+    pub fn add_grooves(&mut self, grooves: HashMap<String, types::Groove>) {
+        self.grooves
+            .extend(grooves.into_iter().map(|(key, mut groove)| {
+                groove.key = Some(key.clone());
+                (key, groove)
+            }));
+    }
+
+    /// Look up the groove by its name in this context and then in
+    /// the parents.
+    pub fn groove(&self, name: &str) -> Option<&types::Groove> {
+        match self.grooves.get(name) {
+            Some(groove) => Some(groove),
+            None => self.parent.as_ref().and_then(|parent| parent.groove(name)),
+        }
+    }
+
+    // A groove is either a name or `{subdivision: 4, offsets: [...],
+    // velocities: [...]}`, `<Nothing>` resets the groove
+    fn groove_value(
+        &self,
+        value: Value,
+    ) -> InterpreterResult<Option<types::Groove>> {
+        let error =
+            |cause: String| InterpreterError::Rule("groove".into(), cause);
+        match value {
+            Value::String(name) => self
+                .groove(&name)
+                .cloned()
+                .map(Some)
+                .ok_or(InterpreterError::UnknownGroove(name)),
+            Value::Properties(properties) => {
+                let get = |name: &str| {
+                    properties.0.get(&Identifier(name.to_string())).cloned()
+                };
+                let numbers = |name: &str| match get(name) {
+                    Some(Value::Array(values)) => values
+                        .into_iter()
+                        .map(|value| match value {
+                            Value::Number(number) => Ok(number),
+                            value => Err(error(format!(
+                                "expected {} as <Number>s, found {}",
+                                name, value
+                            ))),
+                        })
+                        .collect(),
+                    None => Ok(Vec::new()),
+                    Some(value) => Err(error(format!(
+                        "expected {} as <Array>, found {}",
+                        name, value
+                    ))),
+                };
+
+                let subdivision = match get("subdivision") {
+                    Some(Value::Number(number))
+                        if number.fract() == 0.0 && number > 0.0 =>
+                    {
+                        number as u64
+                    }
+                    _ => {
+                        return Err(error(
+                            "expected subdivision as a positive integer".into(),
+                        ))
+                    }
+                };
+                let name = match get("name") {
+                    Some(Value::String(name)) => name,
+                    _ => "groove".to_string(),
+                };
+                let offsets: Vec<i64> = numbers("offsets")?
+                    .into_iter()
+                    .map(|offset| offset.round() as i64)
+                    .collect();
+
+                types::Groove::new(
+                    &name,
+                    subdivision,
+                    &offsets,
+                    &numbers("velocities")?,
+                )
+                .map(Some)
+                .map_err(error)
+            }
+            Value::Nothing => Ok(None),
+            value => Err(error(format!(
+                "expected groove name as <String> or <Properties>, found {}",
+                value.type_id()
+            ))),
+        }
+    }
+
+    // Swing is the percentage of eighths or `{amount: 60, note: 16}`,
+    // `<Nothing>` resets the groove
+    fn swing_value(
+        &self,
+        value: Value,
+    ) -> InterpreterResult<Option<types::Groove>> {
+        let resolution = self.mixer.clock.resolution();
+        let error =
+            |cause: String| InterpreterError::Rule("swing".into(), cause);
+        let (amount, note) = match value {
+            Value::Number(amount) => (amount, 8),
+            Value::Properties(properties) => {
+                let get = |name: &str| {
+                    properties.0.get(&Identifier(name.to_string())).cloned()
+                };
+                match (get("amount"), get("note")) {
+                    (Some(Value::Number(amount)), None) => (amount, 8),
+                    (
+                        Some(Value::Number(amount)),
+                        Some(Value::Number(note)),
+                    ) if note.fract() == 0.0 && note > 0.0 => {
+                        (amount, note as u64)
+                    }
+                    _ => {
+                        return Err(error(
+                            "expected amount and note as <Number>s".into(),
+                        ))
+                    }
+                }
+            }
+            Value::Nothing => return Ok(None),
+            value => {
+                return Err(error(format!(
+                    "expected <Number> or <Properties>, found {}",
+                    value.type_id()
+                )))
+            }
+        };
+
+        types::Groove::swing(amount, note, resolution)
+            .map(Some)
+            .map_err(error)
+    }
+
+    /// Get the property of the `value`. The properties of the mixer,
+    /// the tracks and the slots are looked up in the context's mixer.
+    pub fn property(&self, value: &Value, key: &Identifier) -> Option<Value> {
+        match value {
+            Value::Mixer => self.mixer.property(key),
+            Value::Track(index) => match self.mixer.track(*index) {
+                Some(track) => track.property(key),
+                None => types::Track::default().property(key),
+            },
+            Value::Slot(track, slot) => {
+                match self.mixer.track(*track).and_then(|t| t.slot(*slot)) {
+                    Some(slot) => slot.property(key),
+                    None => types::Slot::default().property(key),
+                }
+            }
+            value => value.property(key),
+        }
+    }
+
+    /// Set the property of the `target`. The mixer, the tracks and
+    /// the slots are changed in the context's mixer, so all the values
+    /// referring to them see the change. Scales, tunings and grooves are
+    /// looked up by their names.
+    pub fn set_property(
+        &mut self,
+        target: &mut Value,
+        key: &Identifier,
+        value: Value,
+    ) -> InterpreterResult<()> {
+        match (target, key.0.as_str()) {
+            (Value::Mixer, _) => self.mixer.set_property(key, value)?,
+            (Value::Track(index), "scale") => {
+                let scale = self.scale_value(value)?;
+                self.mixer.track_mut(*index).set_scale(scale);
+            }
+            (Value::Track(index), "tuning") => {
+                let tuning = self.tuning_value(value)?;
+                self.mixer.track_mut(*index).set_tuning(tuning);
+            }
+            (Value::Track(index), "groove") => {
+                let groove = self.groove_value(value)?;
+                self.mixer.track_mut(*index).set_groove(groove);
+            }
+            (Value::Track(index), "swing") => {
+                let groove = self.swing_value(value)?;
+                self.mixer.track_mut(*index).set_groove(groove);
+            }
+            (Value::Track(index), _) => {
+                self.mixer.track_mut(*index).set_property(key, value)?
+            }
+            (Value::Slot(track, slot), "scale") => {
+                let scale = self.scale_value(value)?;
+                self.mixer
+                    .track_mut(*track)
+                    .slot_mut(*slot)
+                    .pattern_mut()
+                    .set_scale(scale);
+            }
+            (Value::Slot(track, slot), "tuning") => {
+                let tuning = self.tuning_value(value)?;
+                self.mixer
+                    .track_mut(*track)
+                    .slot_mut(*slot)
+                    .pattern_mut()
+                    .set_tuning(tuning);
+            }
+            (Value::Slot(track, slot), "groove") => {
+                let groove = self.groove_value(value)?;
+                self.mixer
+                    .track_mut(*track)
+                    .slot_mut(*slot)
+                    .pattern_mut()
+                    .set_groove(groove);
+            }
+            (Value::Slot(track, slot), "swing") => {
+                let groove = self.swing_value(value)?;
+                self.mixer
+                    .track_mut(*track)
+                    .slot_mut(*slot)
+                    .pattern_mut()
+                    .set_groove(groove);
+            }
+            (Value::Slot(track, slot), _) => self
+                .mixer
+                .track_mut(*track)
+                .slot_mut(*slot)
+                .set_property(key, value)?,
+            (Value::Pattern(pattern), "scale") => {
+                pattern.set_scale(self.scale_value(value)?)
+            }
+            (Value::Pattern(pattern), "tuning") => {
+                pattern.set_tuning(self.tuning_value(value)?)
+            }
+            (Value::Pattern(pattern), "groove") => {
+                pattern.set_groove(self.groove_value(value)?)
+            }
+            (Value::Pattern(pattern), "swing") => {
+                pattern.set_groove(self.swing_value(value)?)
+            }
+            (target, _) => target.set_property(key, value)?,
+        }
+
+        Ok(())
+    }
+
+    /// Call the function with `arguments` checking their types. If there
+    /// are less arguments than the function needs, they're considered as
+    /// the last ones and the function with these arguments bound is
+    /// returned.
+    pub fn call(
+        &self,
+        identifier: &Identifier,
+        arguments: Vec<Value>,
+    ) -> InterpreterResult<Value> {
+        let function = self
+            .function(identifier)
+            .ok_or_else(|| {
+                InterpreterError::UnknownFunction(identifier.0.clone())
+            })?
+            .clone_box();
+
+        let expected = function.arguments();
+        if arguments.len() < expected.len() {
+            check_arguments(
+                function.as_ref(),
+                &expected[expected.len() - arguments.len()..],
+                &arguments,
+            )?;
+            return Ok(Value::Function(Box::new(types::Partial::new(
+                function, arguments,
+            ))));
+        }
+
+        apply(function, arguments)
+    }
+}
+
+/// Call the function with `arguments` checking their types.
+pub fn apply(
+    mut function: Box<dyn Function<Item = Value>>,
+    arguments: Vec<Value>,
+) -> InterpreterResult<Value> {
+    check_arguments(function.as_ref(), &function.arguments(), &arguments)?;
+    function.set_arguments(arguments);
+    function
+        .next()
+        .ok_or_else(|| InterpreterError::NoValue(function.identifier().0))
+}
+
+fn check_arguments(
+    function: &dyn Function<Item = Value>,
+    expected: &[TypeId],
+    arguments: &[Value],
+) -> InterpreterResult<()> {
+    let found: Vec<TypeId> = arguments.iter().map(Value::type_id).collect();
+    if expected == &found[..] {
+        return Ok(());
+    }
+
+    let list = |types: &[TypeId]| {
+        types
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<String>>()
+            .join(", ")
+    };
+    Err(InterpreterError::Arguments(
+        function.identifier().0,
+        list(expected),
+        list(&found),
+    ))
+}
+
 impl<'a> Default for Context<'a> {
     fn default() -> Self {
-        Context {
+        let mut context = Context {
             parent: &None,
             mixer: Mixer::default(),
             variables: VariablesTable::default(),
             functions: HashMap::default(),
+            scales: types::Scale::catalogue(),
+            tunings: HashMap::default(),
+            grooves: HashMap::default(),
+        };
+
+        for function in stdlib::functions().into_iter() {
+            context.register_function(function);
         }
+
+        context
     }
 }
 
 impl Interpreter<()> for ast::Ast {
     fn interpret(self, context: &mut Context<'_>) -> InterpreterResult<()> {
         for statement in self.0.into_iter() {
-            statement.interpret(context)?;
+            let _: Value = statement.interpret(context)?;
         }
         Ok(())
     }
 }
 
-impl Interpreter<()> for ast::Statement {
-    fn interpret(self, context: &mut Context<'_>) -> InterpreterResult<()> {
+/// Assignments are evaluated to `Value::Void`.
+impl Interpreter<Value> for ast::Statement {
+    fn interpret(self, context: &mut Context<'_>) -> InterpreterResult<Value> {
         match self {
-            ast::Statement::SuperExpression(value) => {
-                let _ = value.interpret(context)?;
-                Ok(())
+            ast::Statement::SuperExpression(value) => value.interpret(context),
+            ast::Statement::Assign(value) => {
+                value.interpret(context)?;
+                Ok(Value::Void(()))
             }
-            ast::Statement::Assign(value) => value.interpret(context),
         }
     }
 }
@@ -80,28 +523,58 @@ impl Interpreter<Value> for ast::Expression {
         match self {
             Expression::Boolean(value) => Ok(Value::from(value)),
             Expression::Variable(id) => Ok(context.variables.get(&id.into())),
-            Expression::PatternSuperExpression(value) => unimplemented!(),
+            Expression::PatternSuperExpression(value) => {
+                Ok(Value::from(value.interpret(context)?))
+            }
             Expression::Number(value) => Ok(Value::from(value)),
             Expression::String(value) => Ok(Value::from(value)),
-            Expression::PatternSlot((track_n, slot_n)) => {
-                Expression::interpret_slot(
-                    track_n as usize,
-                    slot_n as usize,
-                    context,
-                )
-            }
-            Expression::Track(index) => {
-                Ok(Value::from(context.mixer.track(index as usize)))
+            Expression::PatternSlot((track, slot)) => {
+                Ok(Value::Slot(track as usize, slot as usize))
             }
+            Expression::Track(index) => Ok(Value::Track(index as usize)),
             Expression::Mixer => Ok(Value::Mixer),
-            // Properties(Properties),
-            // Array(Vec<SuperExpression>),
-            // Function(FunctionExpression),
-            _ => unimplemented!(),
+            Expression::Function(call) => {
+                let arguments = call
+                    .parameters
+                    .into_iter()
+                    .map(|parameter| parameter.interpret(context))
+                    .collect::<InterpreterResult<Vec<Value>>>()?;
+                context.call(&call.identifier.into(), arguments)
+            }
+            Expression::Properties(properties) => {
+                Ok(Value::from(properties.interpret(context)?))
+            }
+            Expression::PropertyGetter {
+                assignee,
+                property_id,
+            } => Expression::interpret_property_getter(
+                *assignee,
+                property_id,
+                context,
+            ),
+            Expression::Array(values) => Ok(Value::from(
+                values
+                    .into_iter()
+                    .map(|value| value.interpret(context))
+                    .collect::<InterpreterResult<Vec<Value>>>()?,
+            )),
         }
     }
 }
 
+impl Interpreter<types::Properties> for ast::Properties {
+    fn interpret(
+        self,
+        context: &mut Context<'_>,
+    ) -> InterpreterResult<types::Properties> {
+        self.0
+            .into_iter()
+            .map(|(key, value)| Ok((key.into(), value.interpret(context)?)))
+            .collect::<InterpreterResult<HashMap<Identifier, Value>>>()
+            .map(types::Properties)
+    }
+}
+
 impl From<ast::Identifier> for Identifier {
     fn from(id: ast::Identifier) -> Self {
         Self(id.0)
@@ -109,30 +582,307 @@ impl From<ast::Identifier> for Identifier {
 }
 
 impl ast::Expression {
-    fn interpret_slot(
-        track_n: usize,
-        slot_n: usize,
+    // Getters can be chained, i.e. `:config.synth.cutoff`.
+    fn interpret_property_getter(
+        assignee: ast::Expression,
+        property_id: Vec<ast::Identifier>,
         context: &mut Context<'_>,
     ) -> InterpreterResult<Value> {
-        match Rc::get_mut(&mut context.mixer.track(track_n)) {
-            Some(track) => Ok(Value::from(track.slot(slot_n))),
-            None => Err(InterpreterError::Rule(
-                "expression".into(),
-                "Cannot get track reference".into(),
-            )),
+        let mut value = assignee.interpret(context)?;
+        for id in property_id.into_iter().map(Identifier::from) {
+            value = context.property(&value, &id).ok_or_else(|| {
+                InterpreterError::UnknownProperty(
+                    id.0.clone(),
+                    value.type_id().to_string(),
+                )
+            })?;
         }
+
+        Ok(value)
     }
 }
 
+/// The caller is passed as the first argument to the first function, then
+/// the result is passed to the next one and so on.
 impl Interpreter<Value> for ast::MethodCall {
     fn interpret(self, context: &mut Context<'_>) -> InterpreterResult<Value> {
-        unimplemented!()
+        let caller = self.caller.interpret(context)?;
+        ast::FunctionCall::chain(caller, self.callee, context)
+    }
+}
+
+impl ast::FunctionCall {
+    fn chain(
+        caller: Value,
+        calls: Vec<ast::FunctionCall>,
+        context: &mut Context<'_>,
+    ) -> InterpreterResult<Value> {
+        calls.into_iter().try_fold(caller, |caller, call| {
+            let mut arguments = vec![caller];
+            for parameter in call.parameters.into_iter() {
+                arguments.push(parameter.interpret(context)?);
+            }
+            context.call(&call.identifier.into(), arguments)
+        })
     }
 }
 
 impl Interpreter<()> for ast::Assignment {
     fn interpret(self, context: &mut Context<'_>) -> InterpreterResult<()> {
-        unimplemented!()
+        match self {
+            ast::Assignment::Variable {
+                assignee,
+                assignment,
+            } => {
+                let value = assignment.interpret(context)?;
+                context.variables.0.insert(assignee.into(), value);
+                Ok(())
+            }
+            ast::Assignment::Pattern {
+                assignee,
+                assignment,
+            } => ast::Assignment::interpret_pattern(
+                assignee, assignment, context,
+            ),
+            ast::Assignment::Properties {
+                assignee,
+                assignment,
+            } => ast::Assignment::interpret_properties(
+                assignee, assignment, context,
+            ),
+        }
+    }
+}
+
+impl ast::Assignment {
+    fn interpret_pattern(
+        assignee: ast::Expression,
+        assignment: ast::PatternSuperExpression,
+        context: &mut Context<'_>,
+    ) -> InterpreterResult<()> {
+        match assignee {
+            ast::Expression::PatternSlot((track_n, slot_n)) => {
+                let mut pattern = assignment.interpret(context)?;
+                pattern.set_loop(true);
+                let track = context.mixer.track_mut(track_n as usize);
+                if let Some(scale) = track.scale() {
+                    pattern.set_default_scale(scale.clone());
+                }
+                if let Some(tuning) = track.tuning() {
+                    pattern.set_default_tuning(tuning.clone());
+                }
+                pattern.set_default_groove(track.groove().cloned());
+                pattern.set_default_velocity(track.velocity());
+                track.slot_mut(slot_n as usize).set_pattern(pattern);
+                track.launch_if_idle(slot_n as usize);
+                Ok(())
+            }
+            _ => Err(InterpreterError::Rule(
+                "pattern assignment".into(),
+                "Pattern can be assigned only to a pattern slot".into(),
+            )),
+        }
+    }
+
+    fn interpret_properties(
+        assignee: ast::SuperExpression,
+        assignment: ast::Properties,
+        context: &mut Context<'_>,
+    ) -> InterpreterResult<()> {
+        let mut target = assignee.clone().interpret(context)?;
+
+        for (key, value) in assignment.0.into_iter() {
+            let value = value.interpret(context)?;
+            context.set_property(&mut target, &key.into(), value)?;
+        }
+
+        if let ast::SuperExpression::Expression(ast::Expression::Variable(id)) =
+            assignee
+        {
+            context.variables.0.insert(id.into(), target);
+        }
+
+        Ok(())
+    }
+}
+
+impl Interpreter<Value> for ast::PropertyValue {
+    fn interpret(self, context: &mut Context<'_>) -> InterpreterResult<Value> {
+        match self {
+            ast::PropertyValue::SuperExpression(value) => {
+                value.interpret(context)
+            }
+            ast::PropertyValue::PatternExpression(value) => {
+                Ok(Value::from(value.interpret(context)?))
+            }
+        }
+    }
+}
+
+impl Interpreter<types::Pattern> for ast::PatternSuperExpression {
+    fn interpret(
+        self,
+        context: &mut Context<'_>,
+    ) -> InterpreterResult<types::Pattern> {
+        match self {
+            ast::PatternSuperExpression::Expression(expression) => {
+                expression.interpret(context)
+            }
+            ast::PatternSuperExpression::ExpressionList(expressions) => {
+                let mut pattern =
+                    types::Pattern::new(context.mixer.clock.cursor().clone());
+                for expression in expressions.into_iter() {
+                    pattern.merge(expression.interpret(context)?);
+                }
+                Ok(pattern)
+            }
+        }
+    }
+}
+
+impl Interpreter<types::Pattern> for ast::PatternExpression {
+    fn interpret(
+        self,
+        context: &mut Context<'_>,
+    ) -> InterpreterResult<types::Pattern> {
+        let pattern = self.pattern.interpret(context)?;
+        let mut pattern = match ast::FunctionCall::chain(
+            Value::from(pattern),
+            self.methods,
+            context,
+        )? {
+            Value::Pattern(pattern) => pattern,
+            value => {
+                return Err(InterpreterError::Rule(
+                    "pattern expression".into(),
+                    format!(
+                        "pattern methods should return <Pattern>, not {}",
+                        value.type_id()
+                    ),
+                ))
+            }
+        };
+
+        if let Some(properties) = self.properties {
+            ast::PatternExpression::interpret_properties(
+                &mut pattern,
+                properties,
+                context,
+            )?;
+        }
+
+        Ok(pattern)
+    }
+}
+
+impl ast::PatternExpression {
+    fn interpret_properties(
+        pattern: &mut types::Pattern,
+        properties: ast::Properties,
+        context: &mut Context<'_>,
+    ) -> InterpreterResult<()> {
+        for (key, value) in properties.0.into_iter() {
+            let value = match (key.0.as_str(), value) {
+                ("scale", value) | ("tuning", value) | ("curve", value) => {
+                    value.interpret(context)?
+                }
+                // the other patterns are modulations
+                (name, ast::PropertyValue::PatternExpression(expression)) => {
+                    let (source, range) =
+                        ast::PatternExpression::interpret_modulation(
+                            expression, context,
+                        )?;
+                    pattern.add_modulation(name, &source, range);
+                    continue;
+                }
+                (_, value) => value.interpret(context)?,
+            };
+
+            match key.0.as_str() {
+                "scale" => pattern.set_scale(context.scale_value(value)?),
+                "tuning" => pattern.set_tuning(context.tuning_value(value)?),
+                "groove" => pattern.set_groove(context.groove_value(value)?),
+                "swing" => pattern.set_groove(context.swing_value(value)?),
+                "curve" => pattern.set_curve(match value {
+                    Value::String(ref name) if name == "linear" => {
+                        types::Curve::Linear
+                    }
+                    Value::String(ref name) if name == "exponential" => {
+                        types::Curve::Exponential
+                    }
+                    value => {
+                        return Err(types::PrimitiveError::SetProperty {
+                            property_name: key.0,
+                            assignee_name: "<Pattern>".to_string(),
+                            cause: format!(
+                                "expected \"linear\" or \"exponential\", \
+                                 found {}",
+                                value
+                            ),
+                        }
+                        .into())
+                    }
+                }),
+                "length" | "loop_start" | "loop_end" => {
+                    pattern.set_property(&Identifier::from(key), value)?
+                }
+                // a number is the velocity of the notes, a pattern is
+                // a modulation
+                "velocity" if !matches!(value, Value::Pattern(_)) => {
+                    pattern.set_property(&Identifier::from(key), value)?
+                }
+                name => match value {
+                    Value::Pattern(source) => {
+                        pattern.add_modulation(name, &source, MODULATION_RANGE)
+                    }
+                    _ => {
+                        return Err(types::PrimitiveError::SetProperty {
+                            property_name: key.0,
+                            assignee_name: "<Pattern>".to_string(),
+                            cause: "unknown property".to_string(),
+                        }
+                        .into())
+                    }
+                },
+            }
+        }
+
+        Ok(())
+    }
+
+    // The `min` and `max` properties of a modulation pattern set the range
+    // its degrees are mapped into.
+    fn interpret_modulation(
+        mut expression: ast::PatternExpression,
+        context: &mut Context<'_>,
+    ) -> InterpreterResult<(types::Pattern, (f64, f64))> {
+        let mut range = MODULATION_RANGE;
+
+        if let Some(properties) = expression.properties.as_mut() {
+            for (name, bound) in
+                [("min", &mut range.0), ("max", &mut range.1)].iter_mut()
+            {
+                let key = ast::Identifier(name.to_string());
+                if let Some(value) = properties.0.remove(&key) {
+                    match value.interpret(context)? {
+                        Value::Number(number) => **bound = number,
+                        value => {
+                            return Err(types::PrimitiveError::SetProperty {
+                                property_name: key.0,
+                                assignee_name: "<Pattern>".to_string(),
+                                cause: format!(
+                                    "expected <Number>, found {}",
+                                    value.type_id()
+                                ),
+                            }
+                            .into())
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok((expression.interpret(context)?, range))
     }
 }
 
@@ -146,13 +896,85 @@ impl Interpreter<types::Pattern> for ast::Pattern {
 
         let mut pattern =
             types::Pattern::new(context.mixer.clock.cursor().clone());
-        // TODO: schedule events
+        // octave changes last until the next change, so every event
+        // should know the octave it sounds at
+        let mut octave = types::Octave::default();
+        let mut intermediates = intermediates;
+        for intermediate in intermediates.iter_mut() {
+            match &intermediate.octave {
+                Some(value) => octave = value.clone(),
+                None => intermediate.octave = Some(octave.clone()),
+            }
+        }
+
+        for intermediate in
+            ast::Pattern::interpret_interpolations(intermediates)?.into_iter()
+        {
+            intermediate.schedule(&mut pattern);
+        }
         pattern.sort();
 
         Ok(pattern)
     }
 }
 
+impl ast::Pattern {
+    // Each sequence of `~` becomes a glide from the previous note to
+    // the next one. The previous note lasts until the end of the glide.
+    fn interpret_interpolations(
+        mut intermediates: Vec<IntermediateEvent>,
+    ) -> InterpreterResult<Vec<IntermediateEvent>> {
+        let is_interpolation = |event: &IntermediateEvent| {
+            matches!(event.value, Audible::Interpolation)
+        };
+        let error = || {
+            InterpreterError::Rule(
+                "interpolation".into(),
+                "~ should be between two notes".into(),
+            )
+        };
+
+        let mut n = 0;
+        while n < intermediates.len() {
+            if !is_interpolation(&intermediates[n]) {
+                n += 1;
+                continue;
+            }
+
+            let mut last = n;
+            while intermediates.get(last + 1).is_some_and(is_interpolation) {
+                last += 1;
+            }
+            let end =
+                intermediates[last].position() + intermediates[last].duration;
+
+            let previous = n.checked_sub(1).ok_or_else(error)?;
+            let (from, from_octave) =
+                intermediates[previous].degree().ok_or_else(error)?;
+            let (to, to_octave) = intermediates
+                .get(last + 1)
+                .and_then(IntermediateEvent::degree)
+                .ok_or_else(error)?;
+
+            intermediates[previous].duration =
+                end - intermediates[previous].position();
+            intermediates.drain(n + 1..=last);
+            let glide = &mut intermediates[n];
+            glide.duration = end - glide.position();
+            glide.value = Audible::Glide(types::Glide {
+                from,
+                to,
+                octaves: to_octave as i64 - from_octave as i64,
+                curve: types::Curve::default(),
+            });
+
+            n += 1;
+        }
+
+        Ok(intermediates)
+    }
+}
+
 #[derive(Debug, Default)]
 struct PatternInnerInterpreter {
     divisor_multiplier: usize,
@@ -205,8 +1027,7 @@ impl PatternInnerInterpreter {
         } else {
             Ok(intermediates
                 .into_iter()
-                .map(|arranged| arranged.values)
-                .flatten()
+                .flat_map(|arranged| arranged.values)
                 .collect())
         }
     }
@@ -267,7 +1088,7 @@ impl TieInterpreter {
     }
 
     fn check_lonely(&self) -> InterpreterResult<()> {
-        if let Some(arranged) = self.intermediates.get(0) {
+        if let Some(arranged) = self.intermediates.first() {
             for event in arranged.values.iter() {
                 if let Audible::Tie = event.value {
                     return Err(InterpreterError::LonelyTie(
@@ -342,7 +1163,7 @@ impl BeatEventInterpreter {
         group
             .into_iter()
             .map(|mut arranged| {
-                arranged.values.iter_mut().for_each(|mut event| {
+                arranged.values.iter_mut().for_each(|event| {
                     event.duration /= divisor;
                     event.beat_position /= divisor;
                 });
@@ -394,7 +1215,7 @@ impl Interpreter<Vec<ArrangedIntermediates>> for EventInterpreter {
         context: &mut Context<'_>,
     ) -> InterpreterResult<Vec<ArrangedIntermediates>> {
         match self.event.clone() {
-            ast::Event::Group(atoms) => self.interpret_group(atoms),
+            ast::Event::Group(atoms) => self.interpret_group(atoms, context),
             ast::Event::Chord(chord) => self.interpret_chord(chord, context),
             ast::Event::ParenthesisedEvent(event) => {
                 self.interpret_parenthesised(event, context)
@@ -407,6 +1228,7 @@ impl EventInterpreter {
     fn interpret_group(
         self,
         atoms: Vec<ast::PatternAtom>,
+        context: &mut Context<'_>,
     ) -> InterpreterResult<Vec<ArrangedIntermediates>> {
         let mut output: Vec<ArrangedIntermediates> = Vec::new();
         let mut atom_interpreter = AtomInterpreter::new(
@@ -416,7 +1238,9 @@ impl EventInterpreter {
         );
 
         for atom in atoms.into_iter() {
-            if let Some(intermediate) = atom_interpreter.interpret(atom)? {
+            if let Some(intermediate) =
+                atom_interpreter.interpret(atom, context)?
+            {
                 output.push(intermediate);
             }
         }
@@ -433,10 +1257,12 @@ impl EventInterpreter {
             self.interpret_inner(event.inner.len(), event.inner, context)?;
         let methods_modifier =
             AtomInterpreter::interpret_methods(1.0, &event.methods);
+        let accent = AtomInterpreter::interpret_accents(&event.methods);
 
         Ok(intermediates
             .into_iter()
             .scan(self.beat_position.borrow_mut(), |position, mut event| {
+                event.accent(accent);
                 event.duration *= methods_modifier;
                 event.beat_position = **position;
                 **position += event.duration;
@@ -466,11 +1292,13 @@ impl EventInterpreter {
         let intermediates = self.interpret_inner(1, chord.inner, context)?;
         let methods_modifier =
             AtomInterpreter::interpret_methods(1.0, &chord.methods);
+        let accent = AtomInterpreter::interpret_accents(&chord.methods);
         let mut position = self.beat_position.borrow_mut();
 
         let values = intermediates
             .into_iter()
             .map(|mut event| {
+                event.accent(accent);
                 event.duration *= methods_modifier;
                 event.beat = self.beat;
                 event.beat_position += *position;
@@ -518,6 +1346,7 @@ impl AtomInterpreter {
     fn interpret(
         &mut self,
         atom: ast::PatternAtom,
+        context: &mut Context<'_>,
     ) -> InterpreterResult<Option<ArrangedIntermediates>> {
         match atom.value {
             ast::PatternAtomValue::Octave(octave) => {
@@ -534,8 +1363,14 @@ impl AtomInterpreter {
             ast::PatternAtomValue::Pause => {
                 Ok(Some(self.next_intermediate(Audible::Pause, &atom.methods)))
             }
-            ast::PatternAtomValue::PatternInput(expression) => unimplemented!(),
-            ast::PatternAtomValue::Interpolation => unimplemented!(),
+            ast::PatternAtomValue::PatternInput(expression) => {
+                let value = expression.interpret(context)?;
+                let values = self.interpret_input(value)?;
+                Ok(Some(self.next_arranged(values, &atom.methods)))
+            }
+            ast::PatternAtomValue::Interpolation => Ok(Some(
+                self.next_intermediate(Audible::Interpolation, &atom.methods),
+            )),
         }
     }
 
@@ -559,20 +1394,109 @@ impl AtomInterpreter {
         &mut self,
         value: Audible,
         methods: &[ast::EventMethod],
+    ) -> ArrangedIntermediates {
+        self.next_arranged(vec![(value, 0.0, 1.0)], methods)
+    }
+
+    // The values are the audibles with their positions and durations
+    // relative to the atom's duration.
+    fn next_arranged(
+        &mut self,
+        values: Vec<(Audible, f64, f64)>,
+        methods: &[ast::EventMethod],
     ) -> ArrangedIntermediates {
         let duration = AtomInterpreter::interpret_methods(1.0, methods);
+        let accent = AtomInterpreter::interpret_accents(methods);
         let mut beat_position = self.position.borrow_mut();
-        let intermediate = IntermediateEvent {
-            value,
+        let octave = self.octave_change.take();
+        let values = values
+            .into_iter()
+            .map(|(value, position, length)| {
+                let mut event = IntermediateEvent {
+                    value,
+                    duration: length * duration,
+                    octave: octave.clone(),
+                    beat_position: *beat_position + position * duration,
+                    beat: self.beat,
+                };
+                event.accent(accent);
+                event
+            })
+            .collect();
+        let result = ArrangedIntermediates {
+            values,
             duration,
-            octave: self.octave_change.take(),
-            beat_position: *beat_position,
             beat: self.beat,
+            beat_position: *beat_position,
         };
 
         *beat_position += duration;
 
-        intermediate.into()
+        result
+    }
+
+    // A number is a degree, an array is a chord, a pattern is squeezed
+    // into the atom and nothing is a pause.
+    fn interpret_input(
+        &self,
+        value: Value,
+    ) -> InterpreterResult<Vec<(Audible, f64, f64)>> {
+        let values = match value {
+            Value::Number(_) => {
+                vec![(Audible::Degree(Self::input_degree(&value)?), 0.0, 1.0)]
+            }
+            Value::Array(values) => values
+                .iter()
+                .map(|value| {
+                    Ok((Audible::Degree(Self::input_degree(value)?), 0.0, 1.0))
+                })
+                .collect::<InterpreterResult<Vec<(Audible, f64, f64)>>>()?,
+            Value::Pattern(pattern) => {
+                let length = pattern.length().as_f64();
+                pattern
+                    .degree_events()
+                    .into_iter()
+                    .map(|(degree, position, duration)| {
+                        (
+                            Audible::Degree(degree),
+                            position.as_f64() / length,
+                            duration.as_f64() / length,
+                        )
+                    })
+                    .collect()
+            }
+            Value::Nothing => Vec::new(),
+            value => {
+                return Err(InterpreterError::Rule(
+                    "pattern input".into(),
+                    format!(
+                        "expected <Number>, <Array>, <Pattern> or <Nothing>, \
+                         found {}",
+                        value.type_id()
+                    ),
+                ))
+            }
+        };
+
+        if values.is_empty() {
+            Ok(vec![(Audible::Pause, 0.0, 1.0)])
+        } else {
+            Ok(values)
+        }
+    }
+
+    fn input_degree(value: &Value) -> InterpreterResult<types::Degree> {
+        match value {
+            Value::Number(number)
+                if *number >= 0.0 && number.fract() == 0.0 =>
+            {
+                Ok(types::Degree::from(*number as u64))
+            }
+            value => Err(InterpreterError::Rule(
+                "pattern input".into(),
+                format!("{} is not a degree", value),
+            )),
+        }
     }
 
     fn interpret_note(&mut self, note: ast::Note) -> types::Degree {
@@ -601,8 +1525,18 @@ impl AtomInterpreter {
                 ast::EventMethod::Multiply => duration * 2.0,
                 ast::EventMethod::Divide => duration / 2.0,
                 ast::EventMethod::Dot => duration * 1.5,
+                ast::EventMethod::Accent | ast::EventMethod::Ghost => duration,
             })
     }
+
+    // accents are positive, ghost notes are negative
+    fn interpret_accents(methods: &[ast::EventMethod]) -> i64 {
+        methods.iter().fold(0, |accent, method| match method {
+            ast::EventMethod::Accent => accent + 1,
+            ast::EventMethod::Ghost => accent - 1,
+            _ => accent,
+        })
+    }
 }
 
 #[derive(Clone, Debug, Default, PartialEq)]
@@ -636,26 +1570,69 @@ struct IntermediateEvent {
 #[derive(Clone, Debug, PartialEq)]
 enum Audible {
     Degree(types::Degree),
+    #[allow(dead_code)]
     Modulation(types::Modulation),
     Pause,
     Tie,
+    // `~` before it's resolved into a glide
+    Interpolation,
+    Glide(types::Glide),
 }
 
 impl IntermediateEvent {
-    fn schedule(mut self, pattern: &mut types::Pattern) {
-        if let Some(octave) = self.octave.take() {
-            //TODO:
+    // in beats
+    fn position(&self) -> f64 {
+        self.beat as f64 + self.beat_position
+    }
+
+    fn accent(&mut self, accent: i64) {
+        if let Audible::Degree(degree) = &mut self.value {
+            degree.accent += accent;
         }
+    }
+
+    fn degree(&self) -> Option<(types::Degree, u64)> {
+        match &self.value {
+            Audible::Degree(degree) => Some((
+                degree.clone(),
+                self.octave
+                    .as_ref()
+                    .map(types::Octave::get_octave_number)
+                    .unwrap_or_default(),
+            )),
+            _ => None,
+        }
+    }
+
+    fn schedule(mut self, pattern: &mut types::Pattern) {
+        let resolution = pattern.resolution();
+        let position = CursorPosition::from_f64(
+            self.beat as f64 + self.beat_position,
+            resolution,
+        );
+        let duration = Duration::from_f64(self.duration, resolution);
 
         match self.value {
-            Audible::Degree(degree) => unimplemented!(),
-            Audible::Modulation(modulation) => unimplemented!(),
+            Audible::Degree(degree) => {
+                // the octave stream mirrors the degree stream, so the
+                // pattern could match them by index
+                if let Some(octave) = self.octave.take() {
+                    pattern.schedule_octave(octave, position, duration);
+                }
+                pattern.schedule_degree(degree, position, duration);
+            }
+            Audible::Modulation(modulation) => {
+                pattern.schedule_modulation(modulation, position, duration)
+            }
+            Audible::Glide(glide) => {
+                pattern.schedule_glide(glide, position, duration)
+            }
             Audible::Pause => (),
             // TODO: there must haven't been any ties at this stage,
             // because they should be handled in the Pattern interpreter
             // but there is a place for a human mistake, so let's
             // think: how to do it better?
-            Audible::Tie => unreachable!(),
+            Audible::Tie | Audible::Interpolation => unreachable!(),
         }
     }
 }
@@ -666,4 +1643,29 @@ pub enum InterpreterError {
     Rule(String, String),
     #[fail(display = "Alone Tie at beat number {}", 0)]
     LonelyTie(u64),
+    #[fail(display = "{}", 0)]
+    Primitive(types::PrimitiveError),
+    #[fail(display = "Unknown function {}", 0)]
+    UnknownFunction(String),
+    #[fail(
+        display = "Wrong arguments for {}: expected ({}), found ({})",
+        0, 1, 2
+    )]
+    Arguments(String, String, String),
+    #[fail(display = "Function {} returned no value", 0)]
+    NoValue(String),
+    #[fail(display = "Unknown scale {}", 0)]
+    UnknownScale(String),
+    #[fail(display = "Unknown tuning {}", 0)]
+    UnknownTuning(String),
+    #[fail(display = "Unknown groove {}", 0)]
+    UnknownGroove(String),
+    #[fail(display = "Unknown property {} of {}", 0, 1)]
+    UnknownProperty(String, String),
+}
+
+impl From<types::PrimitiveError> for InterpreterError {
+    fn from(error: types::PrimitiveError) -> Self {
+        InterpreterError::Primitive(error)
+    }
 }
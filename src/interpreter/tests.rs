@@ -56,6 +56,7 @@ fn interpret_event_group_alterations() {
                 value: Audible::Degree(Degree {
                     value: 10,
                     alteration: 2,
+                    accent: 0,
                 }),
                 duration: 1.0,
                 octave: None,
@@ -66,6 +67,7 @@ fn interpret_event_group_alterations() {
                 value: Audible::Degree(Degree {
                     value: 11,
                     alteration: -2,
+                    accent: 0,
                 }),
                 duration: 1.0,
                 octave: None,
@@ -851,3 +853,1055 @@ fn pattern_inner_lonely_tie_first_beat_parenthesised() {
     let inner_interpreter = PatternInnerInterpreter::new(pattern.0);
     inner_interpreter.interpret(&mut context).unwrap();
 }
+
+#[test]
+fn interpret_variable_assignment() {
+    use std::str::FromStr;
+
+    let mut context = Context::default();
+    let ast = ast::Ast::from_str(":x = 42\n:y = :x").unwrap();
+    ast.interpret(&mut context).unwrap();
+
+    let id = Identifier("y".to_string());
+    match context.variables.get(&id) {
+        Value::Number(value) => assert_eq!(42.0, value),
+        value => panic!("unexpected value {:?}", value),
+    }
+}
+
+#[test]
+fn interpret_properties_and_arrays() {
+    use std::str::FromStr;
+
+    let mut context = Context::default();
+    let ast = ast::Ast::from_str(
+        ":x = {a: 1, b: [true, \"two\", [3]], c: | 0 1 | {scale: \"major\"}}",
+    )
+    .unwrap();
+    ast.interpret(&mut context).unwrap();
+
+    let x = context.variables.get(&Identifier("x".to_string()));
+    assert_eq!(
+        "{a: 1, b: [true, \"two\", [3]], c: <Pattern>}",
+        x.to_string()
+    );
+    match x.property(&Identifier("c".to_string())) {
+        Some(Value::Pattern(pattern)) => {
+            assert_eq!(vec![60, 62], pitches(pattern))
+        }
+        value => panic!("unexpected value {:?}", value),
+    }
+}
+
+#[test]
+fn interpret_property_getters() {
+    use crate::output::TrackOptions;
+    use std::str::FromStr;
+
+    let mut context = Context::default();
+    let ast = ast::Ast::from_str(
+        "$1 {channel: 3, mute: true, volume: 0.5}
+$ {tempo: 90}
+$1@0 | 0 1 2 | {scale: \"major\"}
+$1@0 {root: 2, octave: 4, loop: false}
+:cfg = {synth: {cutoff: 0.5}}
+:channel = $1.channel
+:tempo = $.tempo
+:length = $1@0.length
+:cutoff = :cfg.synth.cutoff
+:scale = $1@0.scale
+:loop = $1@0.loop
+:octave = | o0 |.octave",
+    )
+    .unwrap();
+    ast.interpret(&mut context).unwrap();
+
+    let variable = |name: &str| {
+        context
+            .variables
+            .get(&Identifier(name.to_string()))
+            .to_string()
+    };
+    assert_eq!("3", variable("channel"));
+    assert_eq!("90", variable("tempo"));
+    assert_eq!("3", variable("length"));
+    assert_eq!("0.5", variable("cutoff"));
+    assert_eq!("\"major\"", variable("scale"));
+    assert_eq!("false", variable("loop"));
+    assert_eq!("4", variable("octave"));
+
+    assert_eq!(
+        &TrackOptions {
+            channel: Some(3),
+            mute: true,
+            volume: Some(0.5),
+            ..Default::default()
+        },
+        context.mixer.track_mut(1).options()
+    );
+    let pattern = context.mixer.track_mut(1).slot_mut(0).pattern().clone();
+    assert_eq!(vec![50, 52, 54], pitches(pattern));
+
+    for source in &[
+        "$1 {channel: 16}",
+        "$1 {mute: 1}",
+        "$1 {volume: 2}",
+        "$ {tempo: \"fast\"}",
+        "$ {resolution: 48}",
+        "$1@0 {length: 0}",
+        "$1@0 {loop_start: 4, loop_end: 2}",
+        "$1@0 {root: -1}",
+        ":x = $.swing",
+        ":x = :cfg.synth.resonance",
+    ] {
+        let ast = ast::Ast::from_str(source).unwrap();
+        assert!(ast.interpret(&mut context).is_err(), "{}", source);
+    }
+}
+
+#[test]
+fn interpret_track_and_slot_references() {
+    use std::str::FromStr;
+
+    let mut context = Context::default();
+    let ast = ast::Ast::from_str(
+        ":track = $1
+:slot = $1@0
+$1@0 | 0 1 |
+:track {volume: 0.5}
+:slot {loop: false}
+$1 {channel: 2}
+:channel = :track.channel
+:length = :slot.length",
+    )
+    .unwrap();
+    ast.interpret(&mut context).unwrap();
+
+    let track = context.mixer.track(1).unwrap();
+    assert_eq!(Some(0.5), track.options().volume);
+    assert!(!track.slot(0).unwrap().pattern().is_loop());
+
+    let variable = |name: &str| {
+        context
+            .variables
+            .get(&Identifier(name.to_string()))
+            .to_string()
+    };
+    assert_eq!("2", variable("channel"));
+    assert_eq!("2", variable("length"));
+}
+
+#[test]
+fn interpret_slot_launching() {
+    use std::str::FromStr;
+
+    let mut context = Context::default();
+    let ast = ast::Ast::from_str(
+        "$1@0 | 0 |
+$1@2 | 1 |
+:first = $1.playing
+$1 {playing: 2}
+:second = $1.playing
+:scene = $.scene",
+    )
+    .unwrap();
+    ast.interpret(&mut context).unwrap();
+
+    let variable = |name: &str| {
+        context
+            .variables
+            .get(&Identifier(name.to_string()))
+            .to_string()
+    };
+    assert_eq!("0", variable("first"));
+    assert_eq!("2", variable("second"));
+    assert_eq!("<Nothing>", variable("scene"));
+
+    let ast = ast::Ast::from_str("$1 {playing: 1.5}").unwrap();
+    assert!(ast.interpret(&mut context).is_err());
+}
+
+#[test]
+fn interpret_time_signature_and_quantisation() {
+    use crate::clock::Quantisation;
+    use std::str::FromStr;
+
+    let mut context = Context::default();
+    let ast = ast::Ast::from_str(
+        "$ {beats_per_bar: 3, quantisation: \"2 bars\"}
+:beats = $.beats_per_bar
+:quantisation = $.quantisation",
+    )
+    .unwrap();
+    ast.interpret(&mut context).unwrap();
+
+    assert_eq!(3, context.mixer.clock.beats_per_bar());
+    assert_eq!(Quantisation::Bars(2), context.mixer.clock.quantisation());
+    let variable = |name: &str| {
+        context
+            .variables
+            .get(&Identifier(name.to_string()))
+            .to_string()
+    };
+    assert_eq!("3", variable("beats"));
+    assert_eq!("\"2 bars\"", variable("quantisation"));
+
+    for source in &[
+        "$ {beats_per_bar: 0}",
+        "$ {beats_per_bar: 2.5}",
+        "$ {quantisation: \"0 bars\"}",
+        "$ {quantisation: \"soon\"}",
+        "$ {quantisation: 1}",
+    ] {
+        let ast = ast::Ast::from_str(source).unwrap();
+        assert!(ast.interpret(&mut context).is_err(), "{}", source);
+    }
+}
+
+#[test]
+fn interpret_tempo_ramps() {
+    use crate::clock::CursorPosition;
+    use std::str::FromStr;
+
+    let mut context = Context::default();
+    let ast = ast::Ast::from_str(
+        "$ {tempo: 60, beats_per_bar: 3}
+$ {tempo: {bpm: 120, bars: 2, beats: 1, curve: \"exponential\"}}",
+    )
+    .unwrap();
+    ast.interpret(&mut context).unwrap();
+
+    let clock = &context.mixer.clock;
+    let resolution = clock.resolution();
+    let map = clock.tempo_map();
+    assert_eq!(2, map.segments().len());
+    assert_eq!(Some(types::Curve::Exponential), map.segments()[0].curve);
+    assert_eq!(
+        CursorPosition::from((7, 0, resolution)),
+        map.segments()[1].position
+    );
+    assert_eq!(120.0, map.segments()[1].tempo.get());
+    assert_eq!(60.0, clock.tempo().get());
+
+    for source in &[
+        "$ {tempo: {bars: 2}}",
+        "$ {tempo: {bpm: 120, bars: -1}}",
+        "$ {tempo: {bpm: 120, curve: \"sine\"}}",
+    ] {
+        let ast = ast::Ast::from_str(source).unwrap();
+        assert!(ast.interpret(&mut context).is_err(), "{}", source);
+    }
+}
+
+#[test]
+fn interpret_swing_and_grooves() {
+    use std::str::FromStr;
+
+    let mut context = Context::default();
+    let mut grooves = HashMap::new();
+    grooves.insert(
+        "late".to_string(),
+        types::Groove::new("Late", 1, &[30], &[]).unwrap(),
+    );
+    context.add_grooves(grooves);
+    let ast = ast::Ast::from_str(
+        "$1@0 | 01 | {swing: 75}
+$1@1 | 0123 | {swing: {amount: 75, note: 16}}
+$2 {groove: \"late\"}
+$2@0 | 0 1 |
+$3@0 | 0123 | {groove: {subdivision: 4, offsets: [0, -20]}}
+:track = $2.groove
+:slot = $1@1.groove",
+    )
+    .unwrap();
+    ast.interpret(&mut context).unwrap();
+
+    let starts = |track: usize, slot: usize| -> Vec<u64> {
+        context
+            .mixer
+            .track(track)
+            .and_then(|track| track.slot(slot))
+            .unwrap()
+            .pattern()
+            .degree_events()
+            .into_iter()
+            .map(|(_, position, _)| position.as_ticks())
+            .collect()
+    };
+    assert_eq!(vec![0, 1440], starts(1, 0));
+    assert_eq!(vec![0, 720, 960, 1680], starts(1, 1));
+    assert_eq!(vec![30, 1950], starts(2, 0));
+    assert_eq!(vec![0, 460, 960, 1420], starts(3, 0));
+
+    let variable = |name: &str| {
+        context
+            .variables
+            .get(&Identifier(name.to_string()))
+            .to_string()
+    };
+    assert_eq!("\"late\"", variable("track"));
+    assert!(variable("slot").contains("name: \"swing 75 1/16\""));
+
+    for source in &[
+        "$1@0 {swing: 30}",
+        "$1@0 {swing: {amount: 60, note: 3}}",
+        "$1 {groove: \"tight\"}",
+        "$1 {groove: {offsets: [1]}}",
+    ] {
+        let ast = ast::Ast::from_str(source).unwrap();
+        assert!(ast.interpret(&mut context).is_err(), "{}", source);
+    }
+}
+
+#[test]
+fn interpret_property_round_trips() {
+    use std::str::FromStr;
+
+    let mut context = Context::default();
+    let mut grooves = HashMap::new();
+    grooves.insert(
+        "late".to_string(),
+        types::Groove::new("Late", 1, &[30], &[]).unwrap(),
+    );
+    context.add_grooves(grooves);
+    let ast = ast::Ast::from_str(
+        "$1 {scale: \"dorian\", groove: \"late\"}
+$1@0 | 0 1 2 3 | {scale: \"minor\", swing: 60}
+$1@1 | 0 1 2 3 | {groove: {subdivision: 2, offsets: [0, 10], \
+velocities: [1, 0.5]}}",
+    )
+    .unwrap();
+    ast.interpret(&mut context).unwrap();
+
+    let track_before = context.mixer.track(1).unwrap().clone();
+    let slot_before = |slot: usize| {
+        context.mixer.track(1).unwrap().slot(slot).unwrap().clone()
+    };
+    let (first_before, second_before) = (slot_before(0), slot_before(1));
+
+    let ast = ast::Ast::from_str(
+        "$1 {scale: $1.scale, groove: $1.groove}
+$1@0 {scale: $1@0.scale, groove: $1@0.groove}
+$1@1 {groove: $1@1.groove}",
+    )
+    .unwrap();
+    ast.interpret(&mut context).unwrap();
+
+    let track = context.mixer.track(1).unwrap();
+    assert_eq!(track_before.scale(), track.scale());
+    assert_eq!(track_before.groove(), track.groove());
+    assert_eq!(
+        first_before.pattern().degree_events(),
+        track.slot(0).unwrap().pattern().degree_events()
+    );
+    assert_eq!(
+        second_before.pattern().degree_events(),
+        track.slot(1).unwrap().pattern().degree_events()
+    );
+}
+
+#[test]
+fn interpret_velocities() {
+    use std::str::FromStr;
+    use types::pattern::{EventState, Value};
+
+    let mut context = Context::default();
+    let ast = ast::Ast::from_str(
+        "$1@0 | 0! 1? 2!! [34]? |
+$2 {velocity: 0.5}
+$2@0 | 0 1? |
+$3@0 | 0123 | {velocity: 0.4, groove: {subdivision: 4, offsets: [0], \
+velocities: [1, 0.5]}}
+:track = $2.velocity
+:pattern = $3@0.velocity",
+    )
+    .unwrap();
+    ast.interpret(&mut context).unwrap();
+
+    // the velocities of the notes in percents
+    let velocities = |track: usize| -> Vec<u64> {
+        let mut pattern = context
+            .mixer
+            .track(track)
+            .and_then(|track| track.slot(0))
+            .unwrap()
+            .pattern()
+            .clone();
+        pattern.set_loop(false);
+        pattern
+            .flatten()
+            .filter(|e| e.state() == EventState::On)
+            .filter_map(|e| match e.value() {
+                Value::Pitch(_, velocity) => {
+                    Some((velocity * 100.0).round() as u64)
+                }
+                _ => None,
+            })
+            .collect()
+    };
+    assert_eq!(vec![100, 60, 100, 60, 60], velocities(1));
+    assert_eq!(vec![50, 30], velocities(2));
+    assert_eq!(vec![40, 20, 40, 20], velocities(3));
+
+    let variable = |name: &str| {
+        context
+            .variables
+            .get(&Identifier(name.to_string()))
+            .to_string()
+    };
+    assert_eq!("0.5", variable("track"));
+    assert_eq!("0.4", variable("pattern"));
+
+    for source in &["$1 {velocity: 2}", "$1@0 | 0 | {velocity: \"loud\"}"] {
+        let ast = ast::Ast::from_str(source).unwrap();
+        assert!(ast.interpret(&mut context).is_err(), "{}", source);
+    }
+}
+
+#[test]
+fn interpret_pattern_length_and_loop_points() {
+    use std::str::FromStr;
+    use types::pattern::{EventState, Value};
+
+    let mut context = Context::default();
+    let ast = ast::Ast::from_str(
+        "$1@0 | 0 1 2 | {length: 5}
+$1@1 | 0 1 2 3 | {length: {beats: 2, ticks: 480}, loop_start: 1}
+$1@0 {loop_end: 4.5}
+:length = $1@0.length
+:loop_end = $1@0.loop_end
+:short = $1@1.length
+:loop_start = $1@1.loop_start",
+    )
+    .unwrap();
+    ast.interpret(&mut context).unwrap();
+
+    let variable = |name: &str| {
+        context
+            .variables
+            .get(&Identifier(name.to_string()))
+            .to_string()
+    };
+    assert_eq!("5", variable("length"));
+    assert_eq!("4.5", variable("loop_end"));
+    assert_eq!("2.25", variable("short"));
+    assert_eq!("1", variable("loop_start"));
+
+    let slot = context.mixer.track_mut(1).slot_mut(1);
+    assert_eq!(vec![60, 61, 62], pitches(slot.pattern().clone()));
+    let mut pattern = slot.pattern().clone();
+    pattern.set_loop(true);
+    assert_eq!(
+        vec![60, 61, 62, 61, 62, 61],
+        pattern
+            .flatten()
+            .filter_map(|e| match (e.value(), e.state()) {
+                (Value::Pitch(pitch, _), EventState::On) => {
+                    Some(*pitch as u64)
+                }
+                _ => None,
+            })
+            .take(6)
+            .collect::<Vec<u64>>()
+    );
+}
+
+#[test]
+fn interpret_pattern_assignment() {
+    use std::str::FromStr;
+
+    let mut context = Context::default();
+    let ast = ast::Ast::from_str("$1@0 | 0 1 2 3 |").unwrap();
+    ast.interpret(&mut context).unwrap();
+
+    let slot = context.mixer.track_mut(1).slot_mut(0);
+    assert!(slot.pattern().is_loop());
+}
+
+#[test]
+fn interpret_properties_assignment_type_mismatch() {
+    use std::str::FromStr;
+
+    let mut context = Context::default();
+    let ast = ast::Ast::from_str(":x = 1\n:x {foo: 2}").unwrap();
+
+    assert_eq!(
+        Err(InterpreterError::Primitive(
+            types::PrimitiveError::SetProperty {
+                property_name: "foo".to_string(),
+                assignee_name: "<Number>".to_string(),
+                cause: "the value has no properties".to_string(),
+            }
+        )),
+        ast.interpret(&mut context)
+    );
+}
+
+#[test]
+fn interpret_track_output_property() {
+    use std::str::FromStr;
+
+    let mut context = Context::default();
+    let ast = ast::Ast::from_str("$1 {output: \"log\"}").unwrap();
+    ast.interpret(&mut context).unwrap();
+
+    assert_eq!(Some("log"), context.mixer.track_mut(1).output());
+}
+
+#[test]
+fn interpret_track_osc_properties() {
+    use crate::output::TrackOptions;
+    use std::str::FromStr;
+
+    let mut context = Context::default();
+    let ast =
+        ast::Ast::from_str("$2 {output: \"osc\", port: 9000, prefix: \"/sc\"}")
+            .unwrap();
+    ast.interpret(&mut context).unwrap();
+
+    assert_eq!(
+        &TrackOptions {
+            host: None,
+            port: Some(9000),
+            prefix: Some("/sc".to_string()),
+            ..Default::default()
+        },
+        context.mixer.track_mut(2).options()
+    );
+
+    for source in &["$2 {port: 1.5}", "$2 {host: \"unknown.invalid\"}"] {
+        let ast = ast::Ast::from_str(source).unwrap();
+        assert!(ast.interpret(&mut context).is_err(), "{}", source);
+    }
+    assert_eq!(None, context.mixer.track_mut(2).options().host);
+
+    let ast = ast::Ast::from_str("$2 {host: \"127.0.0.1\"}").unwrap();
+    ast.interpret(&mut context).unwrap();
+    assert_eq!(
+        Some([127, 0, 0, 1].into()),
+        context.mixer.track_mut(2).options().address
+    );
+}
+
+#[test]
+fn interpret_pattern_schedule() {
+    use crate::clock::{Bpm, Clock};
+    use crate::settings;
+    use types::pattern::{Event, EventState, Value, DEFAULT_VELOCITY};
+
+    let resolution = 4;
+    let mut context = Context::default();
+    context.mixer.clock = Clock::new(
+        Bpm::default(),
+        &settings::Clock {
+            resolution,
+            ..Default::default()
+        },
+    );
+    let pattern: ast::Pattern =
+        CollyParser::parse_source_for_rule("| 0 1 [0 4] _ |", Rule::Pattern)
+            .unwrap();
+    let pattern = pattern.interpret(&mut context).unwrap();
+
+    let event = |pitch: u64, tick, state| {
+        Event::new(
+            Value::Pitch(pitch as f64, DEFAULT_VELOCITY),
+            CursorPosition::from_ticks(tick, resolution),
+            state,
+        )
+    };
+    let on = |pitch, tick, duration| {
+        event(pitch, tick, EventState::On)
+            .with_duration(CursorPosition::from_ticks(duration, resolution))
+    };
+
+    let mut expected: Vec<Vec<Event<Value>>> = vec![Vec::new(); 16];
+    expected[0] = vec![on(60, 0, 4)];
+    expected[3] = vec![event(60, 3, EventState::Off)];
+    expected[4] = vec![on(61, 4, 4)];
+    expected[7] = vec![event(61, 7, EventState::Off)];
+    expected[8] = vec![on(60, 8, 8), on(64, 8, 8)];
+    expected[15] = vec![
+        event(60, 15, EventState::Off),
+        event(64, 15, EventState::Off),
+    ];
+
+    assert_eq!(expected, pattern.collect::<Vec<Vec<Event<Value>>>>());
+}
+
+#[test]
+fn interpret_pattern_octaves() {
+    use crate::clock::{Bpm, Clock};
+    use crate::settings;
+    use types::pattern::{EventState, Value, DEFAULT_VELOCITY};
+
+    let resolution = 1;
+    let mut context = Context::default();
+    context.mixer.clock = Clock::new(
+        Bpm::default(),
+        &settings::Clock {
+            resolution,
+            ..Default::default()
+        },
+    );
+    let pattern: ast::Pattern =
+        CollyParser::parse_source_for_rule("| 0 O1 o0 |", Rule::Pattern)
+            .unwrap();
+    let mut pattern = pattern.interpret(&mut context).unwrap();
+    pattern.set_loop(true);
+
+    let pitches: Vec<Value> = pattern
+        .take(6)
+        .flatten()
+        .filter(|e| e.state() == EventState::On)
+        .map(|e| e.value().clone())
+        .collect();
+
+    assert_eq!(
+        vec![
+            Value::Pitch(60.0, DEFAULT_VELOCITY),
+            Value::Pitch(73.0, DEFAULT_VELOCITY),
+            Value::Pitch(60.0, DEFAULT_VELOCITY),
+            Value::Pitch(60.0, DEFAULT_VELOCITY),
+            Value::Pitch(73.0, DEFAULT_VELOCITY),
+            Value::Pitch(60.0, DEFAULT_VELOCITY),
+        ],
+        pitches
+    );
+}
+
+#[derive(Debug, Clone, Default)]
+struct Add {
+    arguments: Vec<Value>,
+}
+
+impl Function for Add {
+    fn identifier(&self) -> Identifier {
+        Identifier("add".to_string())
+    }
+
+    fn arguments(&self) -> Vec<TypeId> {
+        vec![TypeId::Number, TypeId::Number]
+    }
+
+    fn returns(&self) -> TypeId {
+        TypeId::Number
+    }
+
+    fn set_arguments(&mut self, arguments: Vec<Value>) {
+        self.arguments = arguments;
+    }
+}
+
+impl types::Guide for Add {
+    fn description(&self) -> &'static str {
+        "Add two numbers."
+    }
+
+    fn help(&self) -> &'static str {
+        "(add a b)"
+    }
+}
+
+impl Iterator for Add {
+    type Item = Value;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.arguments[..] {
+            [Value::Number(a), Value::Number(b)] => Some(Value::Number(a + b)),
+            _ => None,
+        }
+    }
+}
+
+#[test]
+fn interpret_method_call_chain() {
+    use std::str::FromStr;
+
+    let mut context = Context::default();
+    context.register_function(Box::new(Add::default()));
+    let ast =
+        ast::Ast::from_str(":x = 1 (add 2) (add 3)\n:y = (add :x 4)").unwrap();
+    ast.interpret(&mut context).unwrap();
+
+    match context.variables.get(&Identifier("y".to_string())) {
+        Value::Number(value) => assert_eq!(10.0, value),
+        value => panic!("unexpected value {:?}", value),
+    }
+}
+
+#[test]
+fn interpret_method_call_errors() {
+    use std::str::FromStr;
+
+    let mut context = Context::default();
+    context.register_function(Box::new(Add::default()));
+
+    let ast = ast::Ast::from_str("\"a\" (add 2)").unwrap();
+    assert_eq!(
+        Err(InterpreterError::Arguments(
+            "add".to_string(),
+            "<Number>, <Number>".to_string(),
+            "<String>, <Number>".to_string()
+        )),
+        ast.interpret(&mut context)
+    );
+
+    let ast = ast::Ast::from_str("1 (sub 2)").unwrap();
+    assert_eq!(
+        Err(InterpreterError::UnknownFunction("sub".to_string())),
+        ast.interpret(&mut context)
+    );
+}
+
+#[test]
+fn interpret_pattern_methods() {
+    use crate::clock::{Bpm, Clock};
+    use crate::settings;
+    use types::pattern::{EventState, Value, DEFAULT_VELOCITY};
+
+    let resolution = 1;
+    let mut context = Context::default();
+    context.mixer.clock = Clock::new(
+        Bpm::default(),
+        &settings::Clock {
+            resolution,
+            ..Default::default()
+        },
+    );
+    let expression: ast::PatternExpression =
+        CollyParser::parse_source_for_rule(
+            "| 0 1 2 | reverse (every 2 (transpose 1))",
+            Rule::PatternExpression,
+        )
+        .unwrap();
+    let pattern = expression.interpret(&mut context).unwrap();
+
+    let pitches: Vec<Value> = pattern
+        .flatten()
+        .filter(|e| e.state() == EventState::On)
+        .map(|e| e.value().clone())
+        .collect();
+
+    assert_eq!(
+        vec![63.0, 62.0, 61.0, 62.0, 61.0, 60.0]
+            .into_iter()
+            .map(|pitch| Value::Pitch(pitch, DEFAULT_VELOCITY))
+            .collect::<Vec<Value>>(),
+        pitches
+    );
+}
+
+fn pitches(mut pattern: types::Pattern) -> Vec<u64> {
+    use types::pattern::{EventState, Value};
+
+    pattern.set_loop(false);
+    pattern
+        .flatten()
+        .filter(|e| e.state() == EventState::On)
+        .filter_map(|e| match e.value() {
+            Value::Pitch(pitch, _) => Some(*pitch as u64),
+            _ => None,
+        })
+        .collect()
+}
+
+#[test]
+fn interpret_pattern_scale() {
+    use crate::clock::{Bpm, Clock};
+    use crate::settings;
+
+    let mut context = Context::default();
+    context.mixer.clock = Clock::new(
+        Bpm::default(),
+        &settings::Clock {
+            resolution: 1,
+            ..Default::default()
+        },
+    );
+    let expression: ast::PatternExpression =
+        CollyParser::parse_source_for_rule(
+            "| 0 1 2 3 | {scale: \"dorian\"}",
+            Rule::PatternExpression,
+        )
+        .unwrap();
+    let pattern = expression.interpret(&mut context).unwrap();
+    assert_eq!(vec![60, 62, 63, 65], pitches(pattern));
+
+    let expression: ast::PatternExpression =
+        CollyParser::parse_source_for_rule(
+            "| 0 | {scale: \"unknown\"}",
+            Rule::PatternExpression,
+        )
+        .unwrap();
+    assert_eq!(
+        Err(InterpreterError::UnknownScale("unknown".to_string())),
+        expression.interpret(&mut context)
+    );
+}
+
+#[test]
+fn interpret_track_scale() {
+    use crate::clock::{Bpm, Clock};
+    use crate::settings;
+    use std::str::FromStr;
+
+    let mut context = Context::default();
+    context.mixer.clock = Clock::new(
+        Bpm::default(),
+        &settings::Clock {
+            resolution: 1,
+            ..Default::default()
+        },
+    );
+    context.add_scales(
+        vec![("fifths".to_string(), types::Scale::new("Fifths", &[0, 7]))]
+            .into_iter()
+            .collect(),
+    );
+
+    let ast = ast::Ast::from_str(
+        "$1@0 | 0 1 2 |\n$1 {scale: \"major\"}\n$1@1 | 0 1 2 |\n\
+         $1@2 | 0 1 2 | {scale: \"fifths\"}",
+    )
+    .unwrap();
+    ast.interpret(&mut context).unwrap();
+
+    let track = context.mixer.track_mut(1);
+    match track.property(&Identifier("scale".to_string())) {
+        Some(Value::String(key)) => assert_eq!("major", key),
+        value => panic!("unexpected scale {:?}", value),
+    }
+    assert_eq!(
+        vec![60, 62, 64],
+        pitches(track.slot_mut(0).pattern().clone())
+    );
+    assert_eq!(
+        vec![60, 62, 64],
+        pitches(track.slot_mut(1).pattern().clone())
+    );
+    assert_eq!(
+        vec![60, 67, 72],
+        pitches(track.slot_mut(2).pattern().clone())
+    );
+}
+
+#[test]
+fn interpret_pattern_tuning() {
+    use crate::clock::{Bpm, Clock};
+    use crate::settings;
+    use approx::assert_relative_eq;
+    use types::pattern::{EventState, Value};
+
+    let mut context = Context::default();
+    context.mixer.clock = Clock::new(
+        Bpm::default(),
+        &settings::Clock {
+            resolution: 1,
+            ..Default::default()
+        },
+    );
+    let expression: ast::PatternExpression =
+        CollyParser::parse_source_for_rule(
+            "| 0 1 2 | {tuning: \"19edo\", scale: \"major\"}",
+            Rule::PatternExpression,
+        )
+        .unwrap();
+    let pattern = expression.interpret(&mut context).unwrap();
+
+    let pitches: Vec<f64> = pattern
+        .flatten()
+        .filter(|e| e.state() == EventState::On)
+        .filter_map(|e| match e.value() {
+            Value::Pitch(pitch, _) => Some(*pitch),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(3, pitches.len());
+    assert_relative_eq!(60.0, pitches[0]);
+    assert_relative_eq!(60.0 + 2.0 * 12.0 / 19.0, pitches[1]);
+    assert_relative_eq!(60.0 + 4.0 * 12.0 / 19.0, pitches[2]);
+
+    let expression: ast::PatternExpression =
+        CollyParser::parse_source_for_rule(
+            "| 0 | {tuning: \"pythagorean\"}",
+            Rule::PatternExpression,
+        )
+        .unwrap();
+    assert_eq!(
+        Err(InterpreterError::UnknownTuning("pythagorean".to_string())),
+        expression.interpret(&mut context)
+    );
+}
+
+#[test]
+fn interpret_pattern_inputs() {
+    use crate::clock::{Bpm, Clock};
+    use crate::settings;
+    use std::str::FromStr;
+    use types::pattern::{EventState, Value, DEFAULT_VELOCITY};
+
+    let resolution = 4;
+    let mut context = Context::default();
+    context.mixer.clock = Clock::new(
+        Bpm::default(),
+        &settings::Clock {
+            resolution,
+            ..Default::default()
+        },
+    );
+    ast::Ast::from_str(":x = 2\n:p = | 0 1 |")
+        .unwrap()
+        .interpret(&mut context)
+        .unwrap();
+
+    let pattern: ast::Pattern = CollyParser::parse_source_for_rule(
+        "| {:x} {:p} {:nothing} 0 |",
+        Rule::Pattern,
+    )
+    .unwrap();
+    let notes: Vec<(u64, Value)> = pattern
+        .interpret(&mut context)
+        .unwrap()
+        .enumerate()
+        .flat_map(|(tick, events)| {
+            events
+                .into_iter()
+                .filter(|e| e.state() == EventState::On)
+                .map(move |e| (tick as u64, e.value().clone()))
+        })
+        .collect();
+
+    assert_eq!(
+        vec![
+            (0, Value::Pitch(62.0, DEFAULT_VELOCITY)),
+            (4, Value::Pitch(60.0, DEFAULT_VELOCITY)),
+            (6, Value::Pitch(61.0, DEFAULT_VELOCITY)),
+            (12, Value::Pitch(60.0, DEFAULT_VELOCITY)),
+        ],
+        notes
+    );
+
+    let pattern: ast::Pattern =
+        CollyParser::parse_source_for_rule("| {\"a\"} |", Rule::Pattern)
+            .unwrap();
+    assert!(pattern.interpret(&mut context).is_err());
+}
+
+#[test]
+fn interpret_pattern_interpolation() {
+    use crate::clock::{Bpm, Clock};
+    use crate::settings;
+    use approx::assert_relative_eq;
+    use types::pattern::{EventState, Value};
+
+    let mut context = Context::default();
+    context.mixer.clock = Clock::new(
+        Bpm::default(),
+        &settings::Clock {
+            resolution: 4,
+            ..Default::default()
+        },
+    );
+    let expression: ast::PatternExpression =
+        CollyParser::parse_source_for_rule(
+            "| 0 ~ 7 |",
+            Rule::PatternExpression,
+        )
+        .unwrap();
+    let mut pattern = expression.interpret(&mut context).unwrap();
+    pattern.set_loop(false);
+    let events: Vec<_> = pattern.flatten().collect();
+
+    let notes: Vec<(f64, u64, u64)> = events
+        .iter()
+        .filter(|e| e.state() == EventState::On)
+        .filter_map(|e| match e.value() {
+            Value::Pitch(pitch, _) => {
+                Some((*pitch, e.position().as_ticks(), e.duration().as_ticks()))
+            }
+            _ => None,
+        })
+        .collect();
+    assert_eq!(vec![(60.0, 0, 8), (67.0, 8, 4)], notes);
+
+    let bends: Vec<f64> = events
+        .iter()
+        .filter_map(|e| match e.value() {
+            Value::Bend(semitones) => Some(*semitones),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(4, bends.len());
+    for (expected, bend) in [0.0, 7.0 / 3.0, 14.0 / 3.0, 7.0].iter().zip(bends)
+    {
+        assert_relative_eq!(*expected, bend, epsilon = 1e-9);
+    }
+
+    for source in &["| ~ 7 |", "| 0 ~ |", "| 0 ~ ~ |"] {
+        let expression: ast::PatternExpression =
+            CollyParser::parse_source_for_rule(source, Rule::PatternExpression)
+                .unwrap();
+        assert_eq!(
+            Err(InterpreterError::Rule(
+                "interpolation".into(),
+                "~ should be between two notes".into()
+            )),
+            expression.interpret(&mut context)
+        );
+    }
+}
+
+#[test]
+fn interpret_pattern_modulation() {
+    use crate::clock::{Bpm, Clock};
+    use crate::settings;
+    use types::pattern::{EventState, Value};
+
+    let mut context = Context::default();
+    context.mixer.clock = Clock::new(
+        Bpm::default(),
+        &settings::Clock {
+            resolution: 2,
+            ..Default::default()
+        },
+    );
+    let expression: ast::PatternExpression =
+        CollyParser::parse_source_for_rule(
+            "| 0 1 2 | {cutoff: | 0 ~ f | {min: 10, max: 40}}",
+            Rule::PatternExpression,
+        )
+        .unwrap();
+    let mut pattern = expression.interpret(&mut context).unwrap();
+    pattern.set_loop(false);
+
+    let mut modulations: Vec<(u64, String, f64)> = pattern
+        .flatten()
+        .filter(|e| e.state() == EventState::On)
+        .filter_map(|e| match e.value() {
+            Value::Modulation(name, value) => {
+                Some((e.position().as_ticks(), name.clone(), *value))
+            }
+            _ => None,
+        })
+        .collect();
+    modulations.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    let cutoff = |tick, value| (tick, "cutoff".to_string(), value);
+    assert_eq!(
+        vec![
+            cutoff(0, 10.0),
+            cutoff(2, 10.0),
+            cutoff(3, 40.0),
+            cutoff(4, 40.0)
+        ],
+        modulations
+    );
+
+    let expression: ast::PatternExpression =
+        CollyParser::parse_source_for_rule(
+            "| 0 | {cutoff: | 0 | {min: \"low\"}}",
+            Rule::PatternExpression,
+        )
+        .unwrap();
+    assert!(expression.interpret(&mut context).is_err());
+}
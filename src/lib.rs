@@ -12,6 +12,8 @@
     unused_qualifications,
     unreachable_pub
 )]
+// failure_derive generates its impls inside anonymous constants
+#![allow(non_local_definitions)]
 
 #[macro_use]
 extern crate pest_derive;
@@ -21,6 +23,12 @@ extern crate failure;
 pub mod ast;
 pub mod clock;
 pub mod interpreter;
+pub mod live;
+pub mod midi;
+pub mod output;
 pub mod parser;
+pub mod player;
+pub mod repl;
 pub mod settings;
+pub mod stdlib;
 pub mod types;
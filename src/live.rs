@@ -0,0 +1,454 @@
+//! Live coding session.
+//!
+//! A [Session](struct.Session.html) evaluates a script and keeps the
+//! [Player](../player/struct.Player.html) in sync with it. When the script
+//! is evaluated again, the statements are interpreted starting from the
+//! first changed one, so the following ones see the changed variables,
+//! and only the changed patterns are sent to the player, so they're
+//! swapped at the next beat without stopping the clock. Only
+//! the playing slot of each track is sent, so launching another slot or
+//! a scene switches the patterns at the next beat too.
+//!
+//! The patterns with `{expression}` inputs are interpreted again, when
+//! the player reaches the last beat of each their loop, and the new
+//! patterns are queued to replace them at the loop end, so the next loop
+//! plays the new values of the inputs.
+
+use crate::ast::{Assignment, Ast, Expression, Statement};
+use crate::clock::{Quantisation, TempoMap};
+use crate::interpreter::{Context, Interpreter, InterpreterError};
+use crate::output::TrackOptions;
+use crate::parser::Rule;
+use crate::player::Player;
+use crate::types::{Mixer, Pattern, Value};
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::time::{Duration, SystemTime};
+
+/// How often the script file is checked for changes.
+pub const POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+type LiveResult<T> = Result<T, LiveError>;
+
+pub struct Session<'a> {
+    context: Context<'a>,
+    player: Player,
+    statements: Vec<Statement>,
+    patterns: HashMap<(usize, usize), Pattern>,
+    tracks: HashMap<usize, (Option<String>, TrackOptions)>,
+    tempo_map: TempoMap,
+    beats_per_bar: u64,
+    quantisation: Quantisation,
+    // the statements assigning patterns with inputs by their slots
+    inputs: HashMap<(usize, usize), Statement>,
+}
+
+impl<'a> Session<'a> {
+    /// The `mixer`'s clock should be the same as the player's one, so
+    /// the patterns have the player's resolution.
+    pub fn new(mixer: Mixer, player: Player) -> Self {
+        let mut context = Context::default();
+        let tempo_map = mixer.clock.tempo_map().clone();
+        let beats_per_bar = mixer.clock.beats_per_bar();
+        let quantisation = mixer.clock.quantisation();
+        *context.mixer_mut() = mixer;
+
+        Session {
+            context,
+            player,
+            statements: Vec::new(),
+            patterns: HashMap::new(),
+            tracks: HashMap::new(),
+            tempo_map,
+            beats_per_bar,
+            quantisation,
+            inputs: HashMap::new(),
+        }
+    }
+
+    pub fn player(&self) -> &Player {
+        &self.player
+    }
+
+    pub fn mixer(&self) -> &Mixer {
+        self.context.mixer()
+    }
+
+    pub fn context_mut(&mut self) -> &mut Context<'a> {
+        &mut self.context
+    }
+
+    /// Interpret the statements of the `source` starting from the first
+    /// one, which differs from the previously evaluated source, and send
+    /// the changes to the player. The following statements are
+    /// interpreted again, as they can use the changed variables. Returns
+    /// the number of the interpreted statements.
+    pub fn eval(&mut self, source: &str) -> LiveResult<usize> {
+        let ast = Ast::from_str(source)?;
+        let first = ast
+            .0
+            .iter()
+            .zip(self.statements.iter())
+            .take_while(|(statement, previous)| statement == previous)
+            .count();
+        let count = ast.0.len() - first;
+        self.statements = ast.0;
+
+        for n in first..self.statements.len() {
+            let statement = self.statements[n].clone();
+            if let Err(error) = statement.interpret(&mut self.context) {
+                // the failed statements should be interpreted next time
+                self.statements.truncate(n);
+                self.collect_inputs();
+                self.sync();
+                return Err(error.into());
+            }
+        }
+        self.collect_inputs();
+        self.sync();
+
+        Ok(count)
+    }
+
+    /// Interpret all the statements of the `source` and send the changes
+    /// to the player. Returns the values of the statements.
+    pub fn interpret(&mut self, source: &str) -> LiveResult<Vec<Value>> {
+        let ast = Ast::from_str(source)?;
+        let mut values = Vec::new();
+        let mut result = Ok(());
+        for statement in ast.0.into_iter() {
+            match statement.clone().interpret(&mut self.context) {
+                Ok(value) => {
+                    values.push(value);
+                    self.statements.push(statement);
+                }
+                Err(error) => {
+                    result = Err(error.into());
+                    break;
+                }
+            }
+        }
+        self.collect_inputs();
+        self.sync();
+
+        result.map(|_| values)
+    }
+
+    /// Interpret again the statement, which assigns the pattern with
+    /// inputs to the slot, and queue the new pattern to replace the
+    /// playing one at its loop end.
+    pub fn update_inputs(
+        &mut self,
+        track: usize,
+        slot: usize,
+    ) -> LiveResult<()> {
+        let key = (track, slot);
+        let statement = match self.inputs.get(&key) {
+            Some(statement) if self.patterns.contains_key(&key) => {
+                statement.clone()
+            }
+            _ => return Ok(()),
+        };
+        statement.interpret(&mut self.context)?;
+
+        let pattern = self
+            .context
+            .mixer()
+            .track(track)
+            .and_then(|track| track.slot(slot))
+            .map(|slot| slot.pattern().clone());
+        if let Some(pattern) = pattern {
+            if self.patterns.get(&key) != Some(&pattern) {
+                self.player.queue_pattern(track, slot, pattern.clone());
+                self.patterns.insert(key, pattern);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Evaluate the file at `path` and start playback. The file is
+    /// re-evaluated each time it's modified, the patterns with inputs at
+    /// the ends of their loops. Errors of evaluation are
+    /// reported to `log` and don't stop the session.
+    pub fn run_file<P, W>(&mut self, path: P, log: &mut W) -> io::Result<()>
+    where
+        P: AsRef<Path>,
+        W: io::Write,
+    {
+        let path = path.as_ref();
+        let mut modified = SystemTime::UNIX_EPOCH;
+        let mut is_started = false;
+
+        let (sender, receiver) = mpsc::channel();
+        self.player.on_loop_ending(move |track, slot| {
+            // the session is gone, if the receiver is
+            let _ = sender.send((track, slot));
+        });
+
+        loop {
+            let current = fs::metadata(path)?.modified()?;
+            if current != modified {
+                modified = current;
+                let source = fs::read_to_string(path)?;
+                match self.eval(&source) {
+                    Ok(count) => {
+                        writeln!(log, "Evaluated {} statement(s)", count)?
+                    }
+                    Err(error) => writeln!(log, "Error: {}", error)?,
+                }
+
+                if !is_started {
+                    self.player.start();
+                    is_started = true;
+                }
+            }
+
+            match receiver.recv_timeout(POLL_INTERVAL) {
+                Ok((track, slot)) => {
+                    if let Err(error) = self.update_inputs(track, slot) {
+                        writeln!(log, "Error: {}", error)?;
+                    }
+                }
+                Err(RecvTimeoutError::Timeout) => (),
+                Err(RecvTimeoutError::Disconnected) => return Ok(()),
+            }
+
+            for warning in self.context.mixer_mut().take_warnings() {
+                writeln!(log, "Warning: {}", warning)?;
+            }
+        }
+    }
+
+    // The last statement assigning the slot defines if it has inputs.
+    fn collect_inputs(&mut self) {
+        let mut inputs = HashMap::new();
+        for statement in self.statements.iter() {
+            if let Statement::Assign(Assignment::Pattern {
+                assignee: Expression::PatternSlot((track, slot)),
+                assignment,
+            }) = statement
+            {
+                let key = (*track as usize, *slot as usize);
+                if assignment.has_inputs() {
+                    inputs.insert(key, statement.clone());
+                } else {
+                    inputs.remove(&key);
+                }
+            }
+        }
+        self.inputs = inputs;
+    }
+
+    fn sync(&mut self) {
+        let mixer = self.context.mixer();
+
+        // the mixer's cursor stays at the zero position, so its tempo map
+        // is relative to the moment the script changed it
+        if *mixer.clock.tempo_map() != self.tempo_map {
+            self.tempo_map = mixer.clock.tempo_map().clone();
+            self.player.set_tempo_map(self.tempo_map.clone());
+        }
+
+        if mixer.clock.beats_per_bar() != self.beats_per_bar {
+            self.beats_per_bar = mixer.clock.beats_per_bar();
+            self.player.set_beats_per_bar(self.beats_per_bar);
+        }
+
+        if mixer.clock.quantisation() != self.quantisation {
+            self.quantisation = mixer.clock.quantisation();
+            self.player.set_quantisation(self.quantisation);
+        }
+
+        for (index, track) in mixer.tracks() {
+            let routing =
+                (track.output().map(String::from), track.options().clone());
+            if self.tracks.get(&index) != Some(&routing) {
+                self.player.route(index, routing.0.clone());
+                self.player.configure(index, routing.1.clone());
+                self.tracks.insert(index, routing);
+            }
+
+            let playing = track.playing();
+            let stopped: Vec<(usize, usize)> = self
+                .patterns
+                .keys()
+                .filter(|(t, s)| *t == index && Some(*s) != playing)
+                .cloned()
+                .collect();
+            for (track_index, slot_index) in stopped.into_iter() {
+                self.player.remove_pattern(track_index, slot_index);
+                self.patterns.remove(&(track_index, slot_index));
+            }
+
+            let slot = playing.and_then(|n| track.slot(n).map(|s| (n, s)));
+            if let Some((slot_index, slot)) = slot {
+                let key = (index, slot_index);
+                if self.patterns.get(&key) != Some(slot.pattern()) {
+                    self.player.set_pattern(
+                        index,
+                        slot_index,
+                        slot.pattern().clone(),
+                    );
+                    self.patterns.insert(key, slot.pattern().clone());
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug, Fail)]
+pub enum LiveError {
+    #[fail(display = "{}", 0)]
+    Parse(pest::error::Error<Rule>),
+    #[fail(display = "{}", 0)]
+    Interpreter(InterpreterError),
+}
+
+impl From<pest::error::Error<Rule>> for LiveError {
+    fn from(error: pest::error::Error<Rule>) -> Self {
+        LiveError::Parse(error)
+    }
+}
+
+impl From<InterpreterError> for LiveError {
+    fn from(error: InterpreterError) -> Self {
+        LiveError::Interpreter(error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::{Bpm, Clock};
+    use crate::output::Recorder;
+    use crate::settings;
+
+    fn session<'a>() -> Session<'a> {
+        let clock = Clock::new(Bpm::default(), &settings::Clock::default());
+        let mut mixer = Mixer::default();
+        mixer.clock = clock.clone();
+        Session::new(mixer, Player::new(clock, Box::new(Recorder::default())))
+    }
+
+    #[test]
+    fn eval_changed_statements() {
+        let mut session = session();
+
+        assert_eq!(2, session.eval(":x = 1\n$1@0 | 0 1 |").unwrap());
+        assert_eq!(0, session.eval(":x = 1\n$1@0 | 0 1 |").unwrap());
+        assert_eq!(1, session.eval(":x = 1\n$1@0 | 0 2 |").unwrap());
+        assert_eq!(
+            1,
+            session.eval(":x = 1\n$1@0 | 0 2 |\n$2@1 | 3 |").unwrap()
+        );
+        assert_eq!(2, session.patterns.len());
+    }
+
+    #[test]
+    fn eval_following_statements() {
+        let mut session = session();
+        let slot_pattern = |session: &Session<'_>| {
+            session.patterns.get(&(1, 0)).cloned().unwrap()
+        };
+
+        let source = ":t = 2\n:x = 1\n$1@0 | 0 1 | (transpose :t)";
+        assert_eq!(3, session.eval(source).unwrap());
+        let first = slot_pattern(&session);
+
+        let source = source.replace(":t = 2", ":t = 4");
+        assert_eq!(3, session.eval(&source).unwrap());
+        assert_ne!(first, slot_pattern(&session));
+
+        let source = source.replace(":t = 4", ":t = 2");
+        assert_eq!(3, session.eval(&source).unwrap());
+        assert_eq!(first, slot_pattern(&session));
+    }
+
+    #[test]
+    fn launch_slots_and_scenes() {
+        let mut session = session();
+        let slots = |session: &Session<'_>| {
+            let mut slots: Vec<(usize, usize)> =
+                session.patterns.keys().cloned().collect();
+            slots.sort_unstable();
+            slots
+        };
+
+        let source = "$1@0 | 0 |\n$1@1 | 1 |\n$2@1 | 2 |";
+        session.eval(source).unwrap();
+        assert_eq!(vec![(1, 0), (2, 1)], slots(&session));
+
+        let source = format!("{}\n$ {{scene: 1}}", source);
+        session.eval(&source).unwrap();
+        assert_eq!(vec![(1, 1), (2, 1)], slots(&session));
+        assert_eq!(Some(1), session.mixer().scene());
+
+        let source = format!("{}\n$2 {{playing: false}}", source);
+        session.eval(&source).unwrap();
+        assert_eq!(vec![(1, 1)], slots(&session));
+
+        // editing the pattern of a stopped track doesn't launch it
+        let source = source.replace("| 2 |", "| 3 |");
+        session.eval(&source).unwrap();
+        assert_eq!(vec![(1, 1)], slots(&session));
+
+        let source = format!("{}\n$ {{scene: 0}}", source);
+        session.eval(&source).unwrap();
+        assert_eq!(vec![(1, 0)], slots(&session));
+        assert_eq!(None, session.mixer().track(2).unwrap().playing());
+    }
+
+    #[test]
+    fn keep_session_on_errors() {
+        let mut session = session();
+
+        session.eval(":x = 1\n$1@0 | 0 |").unwrap();
+        assert!(session.eval(":x = 1\n$1@0 | 0 |\n:x {a: 1}").is_err());
+        assert!(session.eval(":x = 1\n$1@0 | 0 |\n:x {a: 1}").is_err());
+        assert!(session.eval(":x = 1\n$1@0 | 0").is_err());
+        assert_eq!(1, session.eval(":x = 1\n$1@0 | 1 |").unwrap());
+    }
+
+    #[test]
+    fn update_inputs() {
+        let mut session = session();
+        let slot_pattern = |session: &Session<'_>| {
+            session.patterns.get(&(1, 0)).cloned().unwrap()
+        };
+
+        session.eval(":x = 1\n$1@0 | 0 {:x} |").unwrap();
+        let first = slot_pattern(&session);
+        // the variable is changed without interpreting the slot again
+        session.interpret(":x = 2").unwrap();
+        assert_eq!(first, slot_pattern(&session));
+
+        // other slots aren't interpreted
+        session.update_inputs(1, 1).unwrap();
+        assert_eq!(first, slot_pattern(&session));
+
+        session.update_inputs(1, 0).unwrap();
+        assert_ne!(first, slot_pattern(&session));
+
+        // without inputs the pattern isn't updated
+        session.eval(":x = 2\n$1@0 | 0 3 |").unwrap();
+        assert!(session.inputs.is_empty());
+    }
+
+    #[test]
+    fn interpret_all_statements() {
+        let mut session = session();
+
+        let values = session.interpret(":x = 1\n$1@0 | 0 {:x} |").unwrap();
+        assert_eq!(2, values.len());
+        let values = session.interpret(":x = 1").unwrap();
+        assert_eq!(1, values.len());
+        assert!(session.interpret(":x {a: 1}").is_err());
+        assert!(session.inputs.contains_key(&(1, 0)));
+        assert_eq!(1, session.patterns.len());
+    }
+}
@@ -0,0 +1,703 @@
+//! Standard MIDI File export. Fractional pitches are sent as pitch bends.
+
+use crate::clock::{CursorPosition, Resolution, TempoMap};
+use crate::output::TrackOptions;
+use crate::types::{
+    pattern::{Event, EventState, Value},
+    Mixer, Pattern,
+};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::ops::Range;
+use std::path::Path;
+
+pub const DEFAULT_CHANNEL: u8 = 0;
+/// The pitch bend range of the receivers in semitones.
+pub const PITCH_BEND_RANGE: f64 = 2.0;
+const PITCH_BEND_CENTER: u16 = 0x2000;
+const MICROSECONDS_PER_MINUTE: f64 = 60_000_000.0;
+const MICROSECONDS_PER_SECOND: f64 = 1_000_000.0;
+/// The number of steps per beat tempo ramps are written with.
+pub const RAMP_STEPS_PER_BEAT: u64 = 8;
+
+/// Render `beats` beats of the playing slots of the `mixer` into a format 1
+/// Standard MIDI File. The first MIDI track holds the tempo and then goes
+/// one MIDI track per track, which would be heard by the player, on its
+/// channel and with its volume.
+pub fn export<W: Write>(
+    mixer: &Mixer,
+    beats: u64,
+    writer: &mut W,
+) -> io::Result<()> {
+    let resolution = mixer.clock.resolution();
+    let mut smf = Smf::new(resolution)?;
+    smf.conductor.tempo_map(
+        mixer.clock.tempo_map(),
+        resolution,
+        beats * resolution,
+    );
+
+    let tracks = mixer.tracks();
+    let is_soloed = tracks.iter().any(|(_, track)| track.options().solo);
+    for (index, track) in tracks {
+        let options = track.options();
+        let is_audible = if is_soloed {
+            options.solo
+        } else {
+            !options.mute
+        };
+        let slot = track.playing().and_then(|slot| track.slot(slot));
+
+        if let (true, Some(slot)) = (is_audible, slot) {
+            let mut midi_track = SmfTrack::default();
+            midi_track.name(&format!("Track {}", index));
+            midi_track.render_pattern(
+                slot.pattern().clone(),
+                beats * resolution,
+                options,
+            );
+            smf.tracks.push(midi_track);
+        }
+    }
+
+    smf.write(writer)
+}
+
+/// The same as [export](fn.export.html), but writes directly to the file
+/// at `path`.
+pub fn export_to_file<P: AsRef<Path>>(
+    mixer: &Mixer,
+    beats: u64,
+    path: P,
+) -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    export(mixer, beats, &mut writer)?;
+    writer.flush()
+}
+
+/// Format 1 Standard MIDI File.
+#[derive(Debug, Clone)]
+pub struct Smf {
+    ppq: u16,
+    /// The first track of the file, which contains tempo events.
+    pub conductor: SmfTrack,
+    pub tracks: Vec<SmfTrack>,
+}
+
+impl Smf {
+    /// The cursor's resolution is used as the PPQ of the file.
+    pub fn new(resolution: Resolution) -> io::Result<Self> {
+        if resolution == 0 || resolution > 0x7fff {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Resolution {} cannot be used as PPQ", resolution),
+            ));
+        }
+
+        Ok(Smf {
+            ppq: resolution as u16,
+            conductor: SmfTrack::default(),
+            tracks: Vec::new(),
+        })
+    }
+
+    pub fn write<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(b"MThd")?;
+        writer.write_all(&6u32.to_be_bytes())?;
+        writer.write_all(&1u16.to_be_bytes())?;
+        writer.write_all(&(self.tracks.len() as u16 + 1).to_be_bytes())?;
+        writer.write_all(&self.ppq.to_be_bytes())?;
+
+        for track in std::iter::once(&self.conductor).chain(self.tracks.iter())
+        {
+            let data = track.encode();
+            writer.write_all(b"MTrk")?;
+            writer.write_all(&(data.len() as u32).to_be_bytes())?;
+            writer.write_all(&data)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// A track of a [Smf](struct.Smf.html). Events can be added in any order,
+/// they're sorted on encoding.
+#[derive(Debug, Clone, Default)]
+pub struct SmfTrack {
+    // (tick, priority, message), note offs have higher priority, so they
+    // don't cut notes started at the same tick
+    events: Vec<(u64, u8, Vec<u8>)>,
+    // the last pitch bend of the channels
+    bends: BTreeMap<u8, u16>,
+    // the bend of the last note of the channels, which comes from
+    // the fractional part of its pitch
+    offsets: BTreeMap<u8, f64>,
+    // the sounding tuned notes as (requested channel, channel, note)
+    sounding: Vec<(u8, u8, u8)>,
+    // the last tempo in microseconds per quarter note, the tempos are
+    // added in the order of time
+    tempo: Option<u32>,
+}
+
+impl SmfTrack {
+    pub fn name(&mut self, name: &str) {
+        let mut message = vec![0xff, 0x03];
+        write_variable_length(name.len() as u64, &mut message);
+        message.extend_from_slice(name.as_bytes());
+        self.events.push((0, 0, message));
+    }
+
+    pub fn tempo(&mut self, tick: u64, bpm: f64) {
+        self.quarter_duration(
+            tick,
+            (MICROSECONDS_PER_MINUTE / bpm).round() as u32,
+        );
+    }
+
+    /// Write the tempo changes of the `map` during the first `ticks`
+    /// ticks. Ramps are written as steps on the grid of
+    /// `RAMP_STEPS_PER_BEAT`, each one with the average tempo of the step,
+    /// so the file has the same timing as the map.
+    pub fn tempo_map(
+        &mut self,
+        map: &TempoMap,
+        resolution: Resolution,
+        ticks: u64,
+    ) {
+        self.tempo_map_range(map, resolution, 0..ticks.max(1), 0);
+    }
+
+    /// Write the tempo changes of the `map` during the `ticks` moved to
+    /// start at the tick `at`.
+    pub fn tempo_map_range(
+        &mut self,
+        map: &TempoMap,
+        resolution: Resolution,
+        ticks: Range<u64>,
+        at: u64,
+    ) {
+        let end = ticks.end;
+        let grid = (resolution / RAMP_STEPS_PER_BEAT).max(1);
+        let position = |tick| CursorPosition::from_ticks(tick, resolution);
+        let at_tick = |position: CursorPosition| {
+            (position.as_f64() * resolution as f64).round() as u64
+        };
+
+        let segments = map.segments();
+        for (index, segment) in segments.iter().enumerate() {
+            let next = segments
+                .get(index + 1)
+                .map_or(end, |next| at_tick(next.position).min(end));
+            let mut tick = at_tick(segment.position).max(ticks.start);
+            while tick < next {
+                let (step_end, micros) = match segment.curve {
+                    Some(_) => {
+                        let step_end = ((tick / grid + 1) * grid).min(next);
+                        let seconds = map.seconds(position(step_end))
+                            - map.seconds(position(tick));
+                        let beats =
+                            (step_end - tick) as f64 / resolution as f64;
+                        (step_end, seconds / beats * MICROSECONDS_PER_SECOND)
+                    }
+                    None => {
+                        (next, MICROSECONDS_PER_MINUTE / segment.tempo.get())
+                    }
+                };
+                self.quarter_duration(
+                    tick - ticks.start + at,
+                    micros.round() as u32,
+                );
+                tick = step_end;
+            }
+        }
+    }
+
+    // The tempo in microseconds per quarter note. The same tempo as
+    // the last one isn't written again.
+    fn quarter_duration(&mut self, tick: u64, micros: u32) {
+        if self.tempo == Some(micros) {
+            return;
+        }
+        self.tempo = Some(micros);
+        let bytes = micros.to_be_bytes();
+        self.events.push((
+            tick,
+            0,
+            vec![0xff, 0x51, 0x03, bytes[1], bytes[2], bytes[3]],
+        ));
+    }
+
+    pub fn note_on(&mut self, tick: u64, channel: u8, pitch: u8, velocity: u8) {
+        self.events.push((
+            tick,
+            2,
+            vec![0x90 | (channel & 0x0f), pitch & 0x7f, velocity & 0x7f],
+        ));
+    }
+
+    /// Add a note on for a fractional `pitch`. The fractional part is sent
+    /// as a pitch bend before the note, if it differs from the channel's
+    /// current bend. The bend is per channel, so like in MPE a note, which
+    /// needs another bend than the notes sounding on the channel, is moved
+    /// to the next channel, which is free or has the same bend. For
+    /// the bends to be correct the notes should be added in the order
+    /// of time.
+    pub fn tuned_note_on(
+        &mut self,
+        tick: u64,
+        channel: u8,
+        pitch: f64,
+        velocity: u8,
+    ) {
+        let (note, bend) = split_pitch(pitch);
+        let requested = channel;
+        let channel = self.channel_for(channel, bend);
+        self.sounding.push((requested, channel, note));
+        self.offsets
+            .insert(channel, pitch.clamp(0.0, 127.0) - f64::from(note));
+        let current = self
+            .bends
+            .get(&channel)
+            .cloned()
+            .unwrap_or(PITCH_BEND_CENTER);
+        if current != bend {
+            self.pitch_bend(tick, channel, bend);
+        }
+        self.note_on(tick, channel, note, velocity);
+    }
+
+    /// Bend the channel by `semitones` relative to the pitch of the last
+    /// note, which is clamped to the
+    /// [PITCH_BEND_RANGE](constant.PITCH_BEND_RANGE.html). The channels,
+    /// which the channel's notes are moved to, are bent too.
+    pub fn bend(&mut self, tick: u64, channel: u8, semitones: f64) {
+        let mut channels: Vec<u8> = self
+            .sounding
+            .iter()
+            .filter(|(requested, _, _)| *requested == channel)
+            .map(|(_, channel, _)| *channel)
+            .collect();
+        channels.sort_unstable();
+        channels.dedup();
+        if channels.is_empty() {
+            channels.push(channel);
+        }
+
+        for channel in channels {
+            let offset =
+                self.offsets.get(&channel).cloned().unwrap_or_default();
+            let value = bend_value(offset + semitones);
+            if self.bends.get(&channel) != Some(&value) {
+                self.pitch_bend(tick, channel, value);
+            }
+        }
+    }
+
+    /// Add a note off for a note added by
+    /// [tuned_note_on](#method.tuned_note_on) on the channel it has been
+    /// moved to.
+    pub fn tuned_note_off(&mut self, tick: u64, channel: u8, pitch: f64) {
+        let note = split_pitch(pitch).0;
+        let position = self
+            .sounding
+            .iter()
+            .position(|sounding| (sounding.0, sounding.2) == (channel, note));
+        let channel = match position {
+            Some(position) => self.sounding.remove(position).1,
+            None => channel,
+        };
+        self.note_off(tick, channel, note);
+    }
+
+    // The first channel from `channel` on, which has no sounding notes
+    // or has the `bend`. It's `channel`, if all the channels are busy.
+    fn channel_for(&self, channel: u8, bend: u16) -> u8 {
+        (0..16)
+            .map(|n| (channel + n) % 16)
+            .find(|candidate| {
+                let is_free = !self
+                    .sounding
+                    .iter()
+                    .any(|(_, channel, _)| channel == candidate);
+                is_free
+                    || self
+                        .bends
+                        .get(candidate)
+                        .cloned()
+                        .unwrap_or(PITCH_BEND_CENTER)
+                        == bend
+            })
+            .unwrap_or(channel)
+    }
+
+    /// The `value` is 14 bit, where `0x2000` is the center.
+    pub fn pitch_bend(&mut self, tick: u64, channel: u8, value: u16) {
+        let value = value.min(0x3fff);
+        self.bends.insert(channel, value);
+        self.events.push((
+            tick,
+            2,
+            vec![
+                0xe0 | (channel & 0x0f),
+                (value & 0x7f) as u8,
+                (value >> 7) as u8,
+            ],
+        ));
+    }
+
+    pub fn note_off(&mut self, tick: u64, channel: u8, pitch: u8) {
+        self.events.push((
+            tick,
+            1,
+            vec![0x80 | (channel & 0x0f), pitch & 0x7f, 0],
+        ));
+    }
+
+    pub fn control_change(
+        &mut self,
+        tick: u64,
+        channel: u8,
+        controller: u8,
+        value: u8,
+    ) {
+        self.events.push((
+            tick,
+            2,
+            vec![0xb0 | (channel & 0x0f), controller & 0x7f, value & 0x7f],
+        ));
+    }
+
+    /// Add an [Event](../types/pattern/struct.Event.html) produced by
+    /// a pattern at `tick`. An off event is considered to last until the
+    /// end of its tick. Modulations, which names aren't controllers
+    /// (i.e. `cc1`, `CC74`, etc.), are skipped.
+    pub fn add_event(
+        &mut self,
+        tick: u64,
+        options: &TrackOptions,
+        event: &Event<Value>,
+    ) {
+        let channel = options.channel();
+        match (event.value(), event.state()) {
+            (Value::Pitch(pitch, value), EventState::On) => self.tuned_note_on(
+                tick,
+                channel,
+                *pitch,
+                options.velocity(*value),
+            ),
+            (Value::Pitch(pitch, _), EventState::Off) => {
+                self.tuned_note_off(tick + 1, channel, *pitch)
+            }
+            (Value::Modulation(name, value), EventState::On) => {
+                if let Some(controller) = controller_number(name) {
+                    self.control_change(
+                        tick,
+                        channel,
+                        controller,
+                        (value.clamp(0.0, 1.0) * 127.0).round() as u8,
+                    );
+                }
+            }
+            (Value::Modulation(..), EventState::Off) => (),
+            (Value::Bend(semitones), EventState::On) => {
+                self.bend(tick, channel, *semitones)
+            }
+            (Value::Bend(_), EventState::Off) => (),
+        }
+    }
+
+    /// Add events of the `pattern` from its start position until `length`
+    /// (in ticks) is reached or the pattern is finished.
+    pub fn render_pattern(
+        &mut self,
+        mut pattern: Pattern,
+        length: u64,
+        options: &TrackOptions,
+    ) {
+        for tick in pattern.start_position().as_ticks()..length {
+            match pattern.next() {
+                Some(events) => events
+                    .iter()
+                    .for_each(|event| self.add_event(tick, options, event)),
+                None => break,
+            }
+        }
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let mut events = self.events.clone();
+        events.sort_by_key(|(tick, priority, _)| (*tick, *priority));
+
+        let mut result = Vec::new();
+        let mut previous_tick = 0;
+        for (tick, _, message) in events.into_iter() {
+            write_variable_length(tick - previous_tick, &mut result);
+            result.extend(message);
+            previous_tick = tick;
+        }
+
+        // end of track
+        result.extend_from_slice(&[0x00, 0xff, 0x2f, 0x00]);
+        result
+    }
+}
+
+/// Split a fractional pitch into the nearest MIDI note and the 14 bit pitch
+/// bend for the rest, given the [PITCH_BEND_RANGE](constant.PITCH_BEND_RANGE.html).
+pub fn split_pitch(pitch: f64) -> (u8, u16) {
+    let pitch = pitch.clamp(0.0, 127.0);
+    let note = pitch.round();
+    (note as u8, bend_value(pitch - note))
+}
+
+fn bend_value(semitones: f64) -> u16 {
+    let bend = f64::from(PITCH_BEND_CENTER)
+        + (semitones / PITCH_BEND_RANGE * f64::from(PITCH_BEND_CENTER)).round();
+    bend.clamp(0.0, 16383.0) as u16
+}
+
+/// Get the MIDI velocity of the `velocity` from 0 to 1.
+pub fn velocity(velocity: f64) -> u8 {
+    (velocity.clamp(0.0, 1.0) * 127.0).round() as u8
+}
+
+/// Get the controller number from a modulation name like `cc1` or `CC74`.
+pub(crate) fn controller_number(name: &str) -> Option<u8> {
+    if name.len() > 2 && name[..2].eq_ignore_ascii_case("cc") {
+        name[2..].parse().ok().filter(|n| *n < 128)
+    } else {
+        None
+    }
+}
+
+fn write_variable_length(value: u64, buffer: &mut Vec<u8>) {
+    let mut bytes = vec![(value & 0x7f) as u8];
+    let mut value = value >> 7;
+    while value > 0 {
+        bytes.push(((value & 0x7f) as u8) | 0x80);
+        value >>= 7;
+    }
+    buffer.extend(bytes.into_iter().rev());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::{Bpm, Clock, Cursor};
+    use crate::settings;
+    use crate::types::{
+        self, Curve, HasProperties, Identifier, Modulation, Track,
+    };
+    use approx::assert_relative_eq;
+
+    #[test]
+    fn variable_length() {
+        let cases: Vec<(u64, Vec<u8>)> = vec![
+            (0, vec![0x00]),
+            (0x40, vec![0x40]),
+            (0x7f, vec![0x7f]),
+            (0x80, vec![0x81, 0x00]),
+            (0x2000, vec![0xc0, 0x00]),
+            (0x0fff_ffff, vec![0xff, 0xff, 0xff, 0x7f]),
+        ];
+
+        for (value, expected) in cases.into_iter() {
+            let mut buffer = Vec::new();
+            write_variable_length(value, &mut buffer);
+            assert_eq!(expected, buffer);
+        }
+    }
+
+    #[test]
+    fn controller_names() {
+        assert_eq!(Some(1), controller_number("CC1"));
+        assert_eq!(Some(74), controller_number("cc74"));
+        assert_eq!(None, controller_number("cc128"));
+        assert_eq!(None, controller_number("cutoff"));
+    }
+
+    #[test]
+    fn split_pitches() {
+        assert_eq!((60, 0x2000), split_pitch(60.0));
+        assert_eq!((61, 0x1800), split_pitch(60.5));
+        assert_eq!((60, 0x2400), split_pitch(60.25));
+        assert_eq!((0, 0x2000), split_pitch(-3.0));
+        assert_eq!((127, 0x2000), split_pitch(130.0));
+    }
+
+    #[test]
+    fn bend_fractional_pitches() {
+        let mut track = SmfTrack::default();
+        track.tuned_note_on(0, 0, 60.0, 100);
+        track.tuned_note_off(1, 0, 60.0);
+        track.tuned_note_on(1, 0, 60.25, 100);
+        track.tuned_note_on(1, 1, 62.0, 100);
+        track.tuned_note_off(2, 0, 60.25);
+        track.tuned_note_on(2, 0, 64.0, 100);
+
+        let mut expected: Vec<u8> = Vec::new();
+        expected.extend_from_slice(&[0x00, 0x90, 60, 100]);
+        expected.extend_from_slice(&[0x01, 0x80, 60, 0]);
+        // 0x2000 + 0.125 * 0x2000 = 0x2400
+        expected.extend_from_slice(&[0x00, 0xe0, 0x00, 0x48]);
+        expected.extend_from_slice(&[0x00, 0x90, 60, 100]);
+        expected.extend_from_slice(&[0x00, 0x91, 62, 100]);
+        expected.extend_from_slice(&[0x01, 0x80, 60, 0]);
+        expected.extend_from_slice(&[0x00, 0xe0, 0x00, 0x40]);
+        expected.extend_from_slice(&[0x00, 0x90, 64, 100]);
+        expected.extend_from_slice(&[0x00, 0xff, 0x2f, 0x00]);
+
+        assert_eq!(expected, track.encode());
+    }
+
+    #[test]
+    fn spread_chord_bends() {
+        let mut track = SmfTrack::default();
+        // 60.25 and 67.25 share the bend, 64.5 needs another one
+        for pitch in &[60.25, 64.5, 67.25] {
+            track.tuned_note_on(0, 0, *pitch, 100);
+        }
+        track.bend(1, 0, 0.25);
+        for pitch in &[60.25, 64.5, 67.25] {
+            track.tuned_note_off(1, 0, *pitch);
+        }
+
+        let mut expected: Vec<u8> = Vec::new();
+        // 0x2000 + 0.125 * 0x2000 = 0x2400
+        expected.extend_from_slice(&[0x00, 0xe0, 0x00, 0x48]);
+        expected.extend_from_slice(&[0x00, 0x90, 60, 100]);
+        // 0x2000 - 0.25 * 0x2000 = 0x1800
+        expected.extend_from_slice(&[0x00, 0xe1, 0x00, 0x30]);
+        expected.extend_from_slice(&[0x00, 0x91, 65, 100]);
+        expected.extend_from_slice(&[0x00, 0x90, 67, 100]);
+        expected.extend_from_slice(&[0x01, 0x80, 60, 0]);
+        expected.extend_from_slice(&[0x00, 0x81, 65, 0]);
+        expected.extend_from_slice(&[0x00, 0x80, 67, 0]);
+        // the glide bends both the channels from their notes' bends
+        expected.extend_from_slice(&[0x00, 0xe0, 0x00, 0x50]);
+        expected.extend_from_slice(&[0x00, 0xe1, 0x00, 0x38]);
+        expected.extend_from_slice(&[0x00, 0xff, 0x2f, 0x00]);
+
+        assert_eq!(expected, track.encode());
+    }
+
+    #[test]
+    fn step_tempo_ramps() {
+        let resolution = 16;
+        let mut map = TempoMap::new(Bpm::new(120.0).unwrap());
+        map.ramp(
+            CursorPosition::from_ticks(resolution, resolution),
+            Bpm::new(60.0).unwrap(),
+            CursorPosition::from_ticks(2 * resolution, resolution),
+            Curve::Linear,
+        );
+        let ticks = 4 * resolution;
+        let mut track = SmfTrack::default();
+        track.tempo_map(&map, resolution, ticks);
+
+        let tempos: Vec<(u64, u32)> = track
+            .events
+            .iter()
+            .map(|(tick, _, message)| {
+                let micros =
+                    u32::from_be_bytes([0, message[3], message[4], message[5]]);
+                (*tick, micros)
+            })
+            .collect();
+        // a beat of 120, 2 beats of the ramp and the rest of 60
+        assert_eq!(2 * RAMP_STEPS_PER_BEAT as usize + 2, tempos.len());
+        assert_eq!((0, 500_000), tempos[0]);
+        assert_eq!((3 * resolution, 1_000_000), tempos[tempos.len() - 1]);
+
+        // the steps keep the timing of the map
+        let seconds: f64 = tempos
+            .iter()
+            .zip(tempos.iter().skip(1).map(|t| t.0).chain(Some(ticks)))
+            .map(|((tick, micros), next)| {
+                (next - tick) as f64 / resolution as f64 * *micros as f64
+                    / MICROSECONDS_PER_SECOND
+            })
+            .sum();
+        let end = CursorPosition::from_ticks(ticks, resolution);
+        assert_relative_eq!(map.seconds(end), seconds, epsilon = 1e-5);
+    }
+
+    #[test]
+    fn export_mixer() {
+        let resolution = 4;
+        let mut mixer = Mixer::default();
+        mixer.clock = Clock::new(
+            Bpm::default(),
+            &settings::Clock {
+                resolution,
+                ..Default::default()
+            },
+        );
+
+        let note = |degree: u64| {
+            let mut pattern = Pattern::new(Cursor::new(resolution));
+            pattern.schedule_degree(
+                degree.into(),
+                (0, 0, resolution).into(),
+                (1, 0, resolution).into(),
+            );
+            pattern
+        };
+        let set = |track: &mut Track, name: &str, value: types::Value| {
+            track.set_property(&Identifier(name.into()), value).unwrap()
+        };
+
+        let mut pattern = note(0);
+        pattern.schedule_modulation(
+            Modulation::new("cc1", 1.0),
+            (0, 2, resolution).into(),
+            (0, 2, resolution).into(),
+        );
+        let track = mixer.track_mut(2);
+        track.slot_mut(0).set_pattern(pattern);
+        // the slots, which aren't playing, are skipped
+        track.slot_mut(1).set_pattern(note(4));
+        track.launch(0);
+        set(track, "channel", types::Value::Number(3.0));
+        set(track, "volume", types::Value::Number(0.5));
+
+        let track = mixer.track_mut(1);
+        track.slot_mut(0).set_pattern(note(7));
+        track.launch(0);
+        set(track, "mute", types::Value::Boolean(true));
+
+        let mut output = Vec::new();
+        export(&mixer, 4, &mut output).unwrap();
+
+        let mut expected: Vec<u8> = Vec::new();
+        expected.extend_from_slice(b"MThd");
+        expected.extend_from_slice(&[0, 0, 0, 6, 0, 1, 0, 2, 0, 4]);
+        // conductor: 60_000_000 / 117 = 512821 = 0x07d335
+        expected.extend_from_slice(b"MTrk");
+        expected.extend_from_slice(&[0, 0, 0, 11]);
+        expected.extend_from_slice(&[0x00, 0xff, 0x51, 0x03, 0x07, 0xd3, 0x35]);
+        expected.extend_from_slice(&[0x00, 0xff, 0x2f, 0x00]);
+        // the track on the channel 3 at the half volume
+        expected.extend_from_slice(b"MTrk");
+        expected.extend_from_slice(&[0, 0, 0, 27]);
+        expected.extend_from_slice(&[0x00, 0xff, 0x03, 0x07]);
+        expected.extend_from_slice(b"Track 2");
+        expected.extend_from_slice(&[0x00, 0x93, 60, 51]);
+        expected.extend_from_slice(&[0x02, 0xb3, 1, 127]);
+        expected.extend_from_slice(&[0x02, 0x83, 60, 0]);
+        expected.extend_from_slice(&[0x00, 0xff, 0x2f, 0x00]);
+
+        assert_eq!(expected, output);
+
+        // only the soloed tracks are exported
+        set(mixer.track_mut(1), "solo", types::Value::Boolean(true));
+        let mut output = Vec::new();
+        export(&mixer, 4, &mut output).unwrap();
+        let contains = |name: &[u8]| {
+            output.windows(name.len()).any(|window| window == name)
+        };
+        assert!(contains(b"Track 1"));
+        assert!(!contains(b"Track 2"));
+    }
+}
@@ -0,0 +1,700 @@
+//! Destinations for the events produced by patterns.
+
+mod osc;
+
+use crate::clock::{Bpm, CursorPosition, Duration, Resolution, TempoMap};
+use crate::midi::{self, Smf, SmfTrack};
+use crate::types::pattern::{Event, EventState, Value};
+pub use osc::*;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::io::{self, Write};
+use std::net::IpAddr;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+/// The name of the output used for tracks without routing.
+pub const DEFAULT_OUTPUT: &str = "default";
+
+/// Output receives pattern events from the [Player](../player/struct.Player.html).
+/// The player calls [tick](#method.tick) at the beginning of each tick
+/// and then sends the events produced at that tick.
+pub trait Output: Send {
+    /// The `velocity` is from 0 to 1. The `duration` is known in advance,
+    /// the corresponding [note_off](#tymethod.note_off) is sent anyway.
+    fn note_on(
+        &mut self,
+        track: usize,
+        slot: usize,
+        pitch: f64,
+        velocity: f64,
+        duration: Duration,
+    );
+    fn note_off(&mut self, track: usize, slot: usize, pitch: f64);
+    fn modulation(&mut self, track: usize, slot: usize, name: &str, value: f64);
+
+    /// Bend the sounding notes of the slot by `semitones` relative to
+    /// their pitches. Outputs, which can't bend notes, ignore it.
+    fn bend(&mut self, _track: usize, _slot: usize, _semitones: f64) {}
+
+    /// Apply output related properties of the track. Outputs ignore
+    /// the options they don't use.
+    fn configure(&mut self, _track: usize, _options: &TrackOptions) {}
+
+    /// The tempo map of the player, it's sent on adding the output and
+    /// on each change. The changes apply from the next tick.
+    fn tempo_map(&mut self, _map: &TempoMap) {}
+
+    fn tick(&mut self, _position: CursorPosition) {}
+    fn start(&mut self) {}
+    fn stop(&mut self) {}
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Dispatch an [Event](../types/pattern/struct.Event.html) to the
+    /// corresponding method. Modulations are sent only on their start.
+    fn event(&mut self, track: usize, slot: usize, event: &Event<Value>) {
+        match (event.value(), event.state()) {
+            (Value::Pitch(pitch, velocity), EventState::On) => {
+                self.note_on(track, slot, *pitch, *velocity, event.duration())
+            }
+            (Value::Pitch(pitch, _), EventState::Off) => {
+                self.note_off(track, slot, *pitch)
+            }
+            (Value::Modulation(name, value), EventState::On) => {
+                self.modulation(track, slot, name, *value)
+            }
+            (Value::Modulation(..), EventState::Off) => (),
+            (Value::Bend(semitones), EventState::On) => {
+                self.bend(track, slot, *semitones)
+            }
+            (Value::Bend(_), EventState::Off) => (),
+        }
+    }
+}
+
+/// Output related properties of a track.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TrackOptions {
+    pub host: Option<String>,
+    /// The address of the `host`, it's resolved, when the host is set, so
+    /// the outputs don't wait for it during the playback.
+    pub address: Option<IpAddr>,
+    pub port: Option<u16>,
+    /// OSC address prefix.
+    pub prefix: Option<String>,
+    /// MIDI channel from 0 to 15.
+    pub channel: Option<u8>,
+    pub mute: bool,
+    pub solo: bool,
+    /// Scales the velocities of the notes, from 0 to 1.
+    pub volume: Option<f64>,
+}
+
+impl TrackOptions {
+    pub fn channel(&self) -> u8 {
+        self.channel.unwrap_or(midi::DEFAULT_CHANNEL)
+    }
+
+    /// The MIDI velocity of the note with `velocity` from 0 to 1 scaled
+    /// by the track's volume.
+    pub fn velocity(&self, velocity: f64) -> u8 {
+        let volume = self.volume.unwrap_or(1.0).clamp(0.0, 1.0);
+        midi::velocity(velocity * volume)
+    }
+}
+
+/// A call of an [Output](trait.Output.html) method.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Message {
+    NoteOn {
+        track: usize,
+        slot: usize,
+        pitch: f64,
+        velocity: f64,
+        duration: Duration,
+    },
+    NoteOff {
+        track: usize,
+        slot: usize,
+        pitch: f64,
+    },
+    Modulation {
+        track: usize,
+        slot: usize,
+        name: String,
+        value: f64,
+    },
+    Bend {
+        track: usize,
+        slot: usize,
+        semitones: f64,
+    },
+    Start,
+    Stop,
+}
+
+impl fmt::Display for Message {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Message::NoteOn {
+                track,
+                slot,
+                pitch,
+                velocity,
+                duration,
+            } => write!(
+                f,
+                "${}@{} note on {} velocity {} for {}",
+                track,
+                slot,
+                pitch,
+                velocity,
+                duration.as_f64()
+            ),
+            Message::NoteOff { track, slot, pitch } => {
+                write!(f, "${}@{} note off {}", track, slot, pitch)
+            }
+            Message::Modulation {
+                track,
+                slot,
+                name,
+                value,
+            } => write!(f, "${}@{} {} {}", track, slot, name, value),
+            Message::Bend {
+                track,
+                slot,
+                semitones,
+            } => write!(f, "${}@{} bend {}", track, slot, semitones),
+            Message::Start => write!(f, "start"),
+            Message::Stop => write!(f, "stop"),
+        }
+    }
+}
+
+/// Keeps all the messages in memory. Clones of the recorder share
+/// the same messages, so one can be passed to the player and another
+/// one can be used to inspect the result.
+#[derive(Debug, Clone, Default)]
+pub struct Recorder {
+    position: CursorPosition,
+    messages: Arc<Mutex<Vec<(CursorPosition, Message)>>>,
+}
+
+impl Recorder {
+    pub fn messages(&self) -> Vec<(CursorPosition, Message)> {
+        self.messages.lock().unwrap().clone()
+    }
+
+    fn push(&mut self, message: Message) {
+        self.messages.lock().unwrap().push((self.position, message));
+    }
+}
+
+impl Output for Recorder {
+    fn note_on(
+        &mut self,
+        track: usize,
+        slot: usize,
+        pitch: f64,
+        velocity: f64,
+        duration: Duration,
+    ) {
+        self.push(Message::NoteOn {
+            track,
+            slot,
+            pitch,
+            velocity,
+            duration,
+        });
+    }
+
+    fn note_off(&mut self, track: usize, slot: usize, pitch: f64) {
+        self.push(Message::NoteOff { track, slot, pitch });
+    }
+
+    fn modulation(
+        &mut self,
+        track: usize,
+        slot: usize,
+        name: &str,
+        value: f64,
+    ) {
+        self.push(Message::Modulation {
+            track,
+            slot,
+            name: name.to_string(),
+            value,
+        });
+    }
+
+    fn bend(&mut self, track: usize, slot: usize, semitones: f64) {
+        self.push(Message::Bend {
+            track,
+            slot,
+            semitones,
+        });
+    }
+
+    fn tick(&mut self, position: CursorPosition) {
+        self.position = position;
+    }
+
+    fn start(&mut self) {
+        self.push(Message::Start);
+    }
+
+    fn stop(&mut self) {
+        self.push(Message::Stop);
+    }
+}
+
+/// Writes messages as text lines prefixed with the position, i.e.
+/// `4.480 $1@0 note on 60 velocity 0.8 for 0.5`.
+#[derive(Debug)]
+pub struct Logger<W: Write + Send> {
+    position: CursorPosition,
+    writer: W,
+}
+
+impl Logger<io::Stdout> {
+    pub fn stdout() -> Self {
+        Logger::new(io::stdout())
+    }
+}
+
+impl<W: Write + Send> Logger<W> {
+    pub fn new(writer: W) -> Self {
+        Logger {
+            position: CursorPosition::default(),
+            writer,
+        }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+
+    fn write(&mut self, message: Message) {
+        // logging shouldn't interrupt the playback
+        let _ = writeln!(
+            self.writer,
+            "{}.{} {}",
+            self.position.beat(),
+            self.position.tick(),
+            message
+        );
+    }
+}
+
+impl<W: Write + Send> Output for Logger<W> {
+    fn note_on(
+        &mut self,
+        track: usize,
+        slot: usize,
+        pitch: f64,
+        velocity: f64,
+        duration: Duration,
+    ) {
+        self.write(Message::NoteOn {
+            track,
+            slot,
+            pitch,
+            velocity,
+            duration,
+        });
+    }
+
+    fn note_off(&mut self, track: usize, slot: usize, pitch: f64) {
+        self.write(Message::NoteOff { track, slot, pitch });
+    }
+
+    fn modulation(
+        &mut self,
+        track: usize,
+        slot: usize,
+        name: &str,
+        value: f64,
+    ) {
+        self.write(Message::Modulation {
+            track,
+            slot,
+            name: name.to_string(),
+            value,
+        });
+    }
+
+    fn tick(&mut self, position: CursorPosition) {
+        self.position = position;
+    }
+
+    fn bend(&mut self, track: usize, slot: usize, semitones: f64) {
+        self.write(Message::Bend {
+            track,
+            slot,
+            semitones,
+        });
+    }
+
+    fn start(&mut self) {
+        self.write(Message::Start);
+    }
+
+    fn stop(&mut self) {
+        self.write(Message::Stop);
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// Records the played events into a Standard MIDI File with one MIDI
+/// track per mixer's track. The file is (re)written on each flush, once
+/// some track is routed to the output. The recording goes on after
+/// stopping and seeking, so the takes follow each other in the file.
+#[derive(Debug)]
+pub struct MidiFileWriter {
+    path: PathBuf,
+    smf: Smf,
+    resolution: Resolution,
+    tracks: BTreeMap<usize, SmfTrack>,
+    options: HashMap<usize, TrackOptions>,
+    tempo_map: TempoMap,
+    // the tick of the recording, it only grows unlike the player's
+    // position
+    tick: u64,
+    // the player's position at the last tick
+    position: Option<u64>,
+    // the parts of the recording, which follow the player's position
+    // with the same tempo map, as (recording tick, position, tempo map)
+    takes: Vec<(u64, u64, TempoMap)>,
+}
+
+impl MidiFileWriter {
+    /// The `resolution` is used as the PPQ of the file.
+    pub fn new<P: Into<PathBuf>>(
+        path: P,
+        tempo: Bpm,
+        resolution: u64,
+    ) -> io::Result<Self> {
+        Ok(MidiFileWriter {
+            path: path.into(),
+            smf: Smf::new(resolution)?,
+            resolution,
+            tracks: BTreeMap::new(),
+            options: HashMap::new(),
+            tempo_map: TempoMap::new(tempo),
+            tick: 0,
+            position: None,
+            takes: Vec::new(),
+        })
+    }
+
+    // The tempo events of the takes.
+    fn conductor(&self) -> SmfTrack {
+        let mut conductor = SmfTrack::default();
+        if self.takes.is_empty() {
+            conductor.tempo_map(&self.tempo_map, self.resolution, 0);
+        }
+        let ends = self.takes.iter().skip(1).map(|take| take.0);
+        for ((at, position, map), end) in
+            self.takes.iter().zip(ends.chain(Some(self.tick + 1)))
+        {
+            let ticks = *position..*position + (end - at);
+            conductor.tempo_map_range(map, self.resolution, ticks, *at);
+        }
+        conductor
+    }
+
+    fn track(&mut self, index: usize) -> &mut SmfTrack {
+        self.tracks.entry(index).or_insert_with(|| {
+            let mut track = SmfTrack::default();
+            track.name(&format!("Track {}", index));
+            track
+        })
+    }
+
+    fn options(&self, track: usize) -> TrackOptions {
+        self.options.get(&track).cloned().unwrap_or_default()
+    }
+}
+
+impl Output for MidiFileWriter {
+    fn note_on(
+        &mut self,
+        track: usize,
+        _slot: usize,
+        pitch: f64,
+        velocity: f64,
+        _duration: Duration,
+    ) {
+        let tick = self.tick;
+        let options = self.options(track);
+        self.track(track).tuned_note_on(
+            tick,
+            options.channel(),
+            pitch,
+            options.velocity(velocity),
+        );
+    }
+
+    fn note_off(&mut self, track: usize, _slot: usize, pitch: f64) {
+        // off events last until the end of their tick
+        let tick = self.tick + 1;
+        let channel = self.options(track).channel();
+        self.track(track).tuned_note_off(tick, channel, pitch);
+    }
+
+    fn modulation(
+        &mut self,
+        track: usize,
+        _slot: usize,
+        name: &str,
+        value: f64,
+    ) {
+        if let Some(controller) = midi::controller_number(name) {
+            let tick = self.tick;
+            let channel = self.options(track).channel();
+            self.track(track).control_change(
+                tick,
+                channel,
+                controller,
+                (value.clamp(0.0, 1.0) * 127.0).round() as u8,
+            );
+        }
+    }
+
+    fn bend(&mut self, track: usize, _slot: usize, semitones: f64) {
+        let tick = self.tick;
+        let channel = self.options(track).channel();
+        self.track(track).bend(tick, channel, semitones);
+    }
+
+    fn configure(&mut self, track: usize, options: &TrackOptions) {
+        self.options.insert(track, options.clone());
+    }
+
+    fn tempo_map(&mut self, map: &TempoMap) {
+        self.tempo_map = map.clone();
+        if let Some(position) = self.position {
+            self.takes.push((
+                self.tick + 1,
+                position + 1,
+                self.tempo_map.clone(),
+            ));
+        }
+    }
+
+    fn tick(&mut self, position: CursorPosition) {
+        let ticks = position.as_ticks();
+        match self.position {
+            Some(last) if ticks == last + 1 => self.tick += 1,
+            // after stopping or seeking the take goes on after the last
+            // tick
+            Some(_) => {
+                self.tick += 1;
+                self.takes.push((self.tick, ticks, self.tempo_map.clone()));
+            }
+            None => self.takes.push((0, ticks, self.tempo_map.clone())),
+        }
+        self.position = Some(ticks);
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if self.tracks.is_empty() {
+            return Ok(());
+        }
+
+        let mut smf = self.smf.clone();
+        smf.conductor = self.conductor();
+        smf.tracks = self.tracks.values().cloned().collect();
+        let mut writer = io::BufWriter::new(std::fs::File::create(&self.path)?);
+        smf.write(&mut writer)?;
+        writer.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dispatch_events() {
+        let resolution = 4;
+        let mut recorder = Recorder::default();
+        let position = CursorPosition::from_ticks(5, resolution);
+        recorder.tick(position);
+        recorder.event(
+            1,
+            2,
+            &Event::new(Value::Pitch(60.0, 0.5), position, EventState::On)
+                .with_duration(Duration::from_ticks(2, resolution)),
+        );
+        recorder.event(
+            1,
+            2,
+            &Event::new(
+                Value::Modulation("cc1".into(), 0.5),
+                position,
+                EventState::Off,
+            ),
+        );
+        recorder.event(
+            1,
+            2,
+            &Event::new(Value::Pitch(60.0, 0.5), position, EventState::Off),
+        );
+
+        assert_eq!(
+            vec![
+                (
+                    position,
+                    Message::NoteOn {
+                        track: 1,
+                        slot: 2,
+                        pitch: 60.0,
+                        velocity: 0.5,
+                        duration: Duration::from_ticks(2, resolution),
+                    }
+                ),
+                (
+                    position,
+                    Message::NoteOff {
+                        track: 1,
+                        slot: 2,
+                        pitch: 60.0
+                    }
+                ),
+            ],
+            recorder.messages()
+        );
+    }
+
+    #[test]
+    fn log_messages() {
+        let mut logger = Logger::new(Vec::new());
+        logger.start();
+        logger.tick(CursorPosition::from_ticks(9, 4));
+        logger.note_on(0, 3, 62.0, 0.8, Duration::from_ticks(2, 4));
+        logger.modulation(1, 0, "cutoff", 0.25);
+
+        assert_eq!(
+            "0.0 start\n2.1 $0@3 note on 62 velocity 0.8 for 0.5\n\
+             2.1 $1@0 cutoff 0.25\n",
+            String::from_utf8(logger.into_inner()).unwrap()
+        );
+    }
+
+    #[test]
+    fn record_takes() {
+        let resolution = 4;
+        let path = std::env::temp_dir()
+            .join(format!("colly-takes-{}.mid", std::process::id()));
+        let mut writer =
+            MidiFileWriter::new(&path, Bpm::default(), resolution).unwrap();
+
+        let position = |tick| CursorPosition::from_ticks(tick, resolution);
+        let note = |writer: &mut MidiFileWriter, tick| {
+            writer.tick(position(tick));
+            writer.note_on(0, 0, 60.0, 1.0, Duration::from_ticks(1, 4));
+            writer.note_off(0, 0, 60.0);
+        };
+        note(&mut writer, 0);
+        note(&mut writer, 1);
+        writer.tempo_map(&TempoMap::new(Bpm::new(60.0).unwrap()));
+        note(&mut writer, 2);
+        // stopped, the position is back at the zero
+        note(&mut writer, 0);
+        writer.flush().unwrap();
+
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let mut expected: Vec<u8> = Vec::new();
+        expected.extend_from_slice(b"MThd");
+        expected.extend_from_slice(&[0, 0, 0, 6, 0, 1, 0, 2, 0, 4]);
+        // the tempo changes at the third tick only
+        expected.extend_from_slice(b"MTrk");
+        expected.extend_from_slice(&[0, 0, 0, 18]);
+        expected.extend_from_slice(&[0x00, 0xff, 0x51, 0x03, 0x07, 0xd3, 0x35]);
+        expected.extend_from_slice(&[0x02, 0xff, 0x51, 0x03, 0x0f, 0x42, 0x40]);
+        expected.extend_from_slice(&[0x00, 0xff, 0x2f, 0x00]);
+        // the notes of the second take follow the first ones
+        expected.extend_from_slice(b"MTrk");
+        expected.extend_from_slice(&[0, 0, 0, 47]);
+        expected.extend_from_slice(&[0x00, 0xff, 0x03, 0x07]);
+        expected.extend_from_slice(b"Track 0");
+        for _ in 0..4 {
+            expected.extend_from_slice(&[0x00, 0x90, 60, 127]);
+            expected.extend_from_slice(&[0x01, 0x80, 60, 0]);
+        }
+        expected.extend_from_slice(&[0x00, 0xff, 0x2f, 0x00]);
+
+        assert_eq!(expected, bytes);
+    }
+
+    #[test]
+    fn write_midi_file() {
+        let resolution = 4;
+        let path = std::env::temp_dir()
+            .join(format!("colly-write-{}.mid", std::process::id()));
+        let mut writer =
+            MidiFileWriter::new(&path, Bpm::default(), resolution).unwrap();
+        writer.configure(
+            1,
+            &TrackOptions {
+                channel: Some(2),
+                ..Default::default()
+            },
+        );
+
+        // nothing is written before the events
+        writer.flush().unwrap();
+        assert!(!path.exists());
+
+        let position = |tick| CursorPosition::from_ticks(tick, resolution);
+        writer.start();
+        writer.tick(position(0));
+        writer.event(
+            1,
+            0,
+            &Event::new(Value::Pitch(60.0, 1.0), position(0), EventState::On)
+                .with_duration(Duration::from_ticks(2, resolution)),
+        );
+        writer.modulation(1, 0, "cc1", 0.5);
+        writer.tick(position(1));
+        writer.event(
+            1,
+            0,
+            &Event::new(Value::Pitch(60.0, 1.0), position(1), EventState::Off),
+        );
+        writer.stop();
+        writer.flush().unwrap();
+
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let mut expected: Vec<u8> = Vec::new();
+        expected.extend_from_slice(b"MThd");
+        expected.extend_from_slice(&[0, 0, 0, 6, 0, 1, 0, 2, 0, 4]);
+        // conductor: 60_000_000 / 117 = 512821 = 0x07d335
+        expected.extend_from_slice(b"MTrk");
+        expected.extend_from_slice(&[0, 0, 0, 11]);
+        expected.extend_from_slice(&[0x00, 0xff, 0x51, 0x03, 0x07, 0xd3, 0x35]);
+        expected.extend_from_slice(&[0x00, 0xff, 0x2f, 0x00]);
+        // the track on the channel 2
+        expected.extend_from_slice(b"MTrk");
+        expected.extend_from_slice(&[0, 0, 0, 27]);
+        expected.extend_from_slice(&[0x00, 0xff, 0x03, 0x07]);
+        expected.extend_from_slice(b"Track 1");
+        expected.extend_from_slice(&[0x00, 0x92, 60, 127]);
+        expected.extend_from_slice(&[0x00, 0xb2, 1, 64]);
+        expected.extend_from_slice(&[0x02, 0x82, 60, 0]);
+        expected.extend_from_slice(&[0x00, 0xff, 0x2f, 0x00]);
+
+        assert_eq!(expected, bytes);
+    }
+}
@@ -0,0 +1,352 @@
+use super::{Output, TrackOptions};
+use crate::clock::Duration;
+use crate::midi;
+use crate::settings;
+use std::collections::HashMap;
+use std::io;
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+
+/// Sends events as OSC messages over UDP:
+///
+/// - `<prefix>/note track slot pitch velocity dur` on note on, where
+///   `pitch` is a fractional MIDI note number, `velocity` is scaled by
+///   the track's `volume` and `dur` is the note's length in beats;
+/// - `<prefix>/mod track name value` on modulation start;
+/// - `<prefix>/bend track slot semitones` during glides.
+///
+/// Note offs aren't sent, because the receiver knows the length of the
+/// note from the start. The destination is taken from the
+/// [settings](../settings/struct.Osc.html) and can be overridden per track
+/// via the track's `host`, `port` and `prefix` properties. The default
+/// host is resolved, when the output is created, and the tracks' ones,
+/// when they're set, so the tracks with unknown hosts are silent.
+#[derive(Debug)]
+pub struct Osc {
+    socket: UdpSocket,
+    defaults: settings::Osc,
+    address: SocketAddr,
+    tracks: HashMap<usize, TrackOptions>,
+    // the tracks without an address are the ones with unknown hosts
+    addresses: HashMap<usize, SocketAddr>,
+}
+
+impl Osc {
+    pub fn new(settings: &settings::Osc) -> io::Result<Self> {
+        Ok(Osc {
+            socket: UdpSocket::bind(("0.0.0.0", 0))?,
+            defaults: settings.clone(),
+            address: resolve(&settings.host, settings.port)?,
+            tracks: HashMap::new(),
+            addresses: HashMap::new(),
+        })
+    }
+
+    fn send(&self, track: usize, name: &str, arguments: &[Argument]) {
+        let (prefix, address) = match self.tracks.get(&track) {
+            Some(options) => (
+                options.prefix.as_ref().unwrap_or(&self.defaults.prefix),
+                self.addresses.get(&track),
+            ),
+            None => (&self.defaults.prefix, Some(&self.address)),
+        };
+
+        if let Some(address) = address {
+            let packet = encode(&format!("{}/{}", prefix, name), arguments);
+            // a missing receiver shouldn't interrupt the playback
+            let _ = self.socket.send_to(&packet, address);
+        }
+    }
+}
+
+/// Resolve the address of the `host`, i.e. `127.0.0.1` or `localhost`.
+pub fn resolve(host: &str, port: u16) -> io::Result<SocketAddr> {
+    (host, port).to_socket_addrs()?.next().ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("cannot resolve host {}", host),
+        )
+    })
+}
+
+impl Output for Osc {
+    fn note_on(
+        &mut self,
+        track: usize,
+        slot: usize,
+        pitch: f64,
+        velocity: f64,
+        duration: Duration,
+    ) {
+        let velocity = self
+            .tracks
+            .get(&track)
+            .map_or(midi::velocity(velocity), |options| {
+                options.velocity(velocity)
+            });
+        self.send(
+            track,
+            "note",
+            &[
+                Argument::Int(track as i32),
+                Argument::Int(slot as i32),
+                Argument::Float(pitch as f32),
+                Argument::Int(i32::from(velocity)),
+                Argument::Float(duration.as_f64() as f32),
+            ],
+        );
+    }
+
+    fn note_off(&mut self, _track: usize, _slot: usize, _pitch: f64) {}
+
+    fn modulation(
+        &mut self,
+        track: usize,
+        _slot: usize,
+        name: &str,
+        value: f64,
+    ) {
+        self.send(
+            track,
+            "mod",
+            &[
+                Argument::Int(track as i32),
+                Argument::String(name.to_string()),
+                Argument::Float(value as f32),
+            ],
+        );
+    }
+
+    fn bend(&mut self, track: usize, slot: usize, semitones: f64) {
+        self.send(
+            track,
+            "bend",
+            &[
+                Argument::Int(track as i32),
+                Argument::Int(slot as i32),
+                Argument::Float(semitones as f32),
+            ],
+        );
+    }
+
+    fn configure(&mut self, track: usize, options: &TrackOptions) {
+        self.tracks.insert(track, options.clone());
+        let address = match options.host {
+            Some(_) => options.address,
+            None => Some(self.address.ip()),
+        };
+        let port = options.port.unwrap_or(self.address.port());
+        match address {
+            Some(address) => {
+                self.addresses.insert(track, SocketAddr::new(address, port))
+            }
+            None => self.addresses.remove(&track),
+        };
+    }
+}
+
+/// An argument of an OSC message.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Argument {
+    Int(i32),
+    Float(f32),
+    String(String),
+}
+
+/// Encode an OSC message.
+pub fn encode(address: &str, arguments: &[Argument]) -> Vec<u8> {
+    let mut result = Vec::new();
+    write_string(address, &mut result);
+
+    let tags: String = std::iter::once(',')
+        .chain(arguments.iter().map(|argument| match argument {
+            Argument::Int(_) => 'i',
+            Argument::Float(_) => 'f',
+            Argument::String(_) => 's',
+        }))
+        .collect();
+    write_string(&tags, &mut result);
+
+    for argument in arguments.iter() {
+        match argument {
+            Argument::Int(value) => result.extend(&value.to_be_bytes()),
+            Argument::Float(value) => {
+                result.extend(&value.to_bits().to_be_bytes())
+            }
+            Argument::String(value) => write_string(value, &mut result),
+        }
+    }
+
+    result
+}
+
+// OSC strings are null terminated and padded to 4 bytes
+fn write_string(value: &str, buffer: &mut Vec<u8>) {
+    buffer.extend_from_slice(value.as_bytes());
+    let padding = 4 - value.len() % 4;
+    buffer.resize(buffer.len() + padding, 0);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::IpAddr;
+    use std::time;
+
+    fn listener() -> (UdpSocket, u16) {
+        let socket = UdpSocket::bind(("127.0.0.1", 0)).unwrap();
+        socket
+            .set_read_timeout(Some(time::Duration::from_secs(1)))
+            .unwrap();
+        let port = socket.local_addr().unwrap().port();
+        (socket, port)
+    }
+
+    fn receive(socket: &UdpSocket) -> Vec<u8> {
+        let mut buffer = [0; 1024];
+        let size = socket.recv(&mut buffer).unwrap();
+        buffer[..size].to_vec()
+    }
+
+    fn encode_modulation(
+        address: &str,
+        track: i32,
+        name: &str,
+        value: f32,
+    ) -> Vec<u8> {
+        encode(
+            address,
+            &[
+                Argument::Int(track),
+                Argument::String(name.to_string()),
+                Argument::Float(value),
+            ],
+        )
+    }
+
+    #[test]
+    fn encode_message() {
+        let mut expected: Vec<u8> = Vec::new();
+        expected.extend_from_slice(b"/colly/mod\0\0");
+        expected.extend_from_slice(b",isf\0\0\0\0");
+        expected.extend_from_slice(&[0, 0, 0, 2]);
+        expected.extend_from_slice(b"cc1\0");
+        expected.extend_from_slice(&[0x3f, 0, 0, 0]);
+
+        assert_eq!(
+            expected,
+            encode(
+                "/colly/mod",
+                &[
+                    Argument::Int(2),
+                    Argument::String("cc1".to_string()),
+                    Argument::Float(0.5),
+                ],
+            )
+        );
+    }
+
+    #[test]
+    fn send_to_listener() {
+        let (socket, port) = listener();
+        let mut osc = Osc::new(&settings::Osc {
+            host: "127.0.0.1".to_string(),
+            port,
+            ..Default::default()
+        })
+        .unwrap();
+
+        osc.note_on(1, 2, 60.0, 0.5, Duration::from_ticks(3, 2));
+        osc.note_off(1, 2, 60.0);
+        osc.modulation(1, 2, "cutoff", 0.25);
+
+        assert_eq!(
+            encode(
+                "/colly/note",
+                &[
+                    Argument::Int(1),
+                    Argument::Int(2),
+                    Argument::Float(60.0),
+                    Argument::Int(64),
+                    Argument::Float(1.5),
+                ]
+            ),
+            receive(&socket)
+        );
+        assert_eq!(
+            encode_modulation("/colly/mod", 1, "cutoff", 0.25),
+            receive(&socket)
+        );
+    }
+
+    #[test]
+    fn track_options() {
+        let (default_socket, default_port) = listener();
+        let (track_socket, track_port) = listener();
+        let mut osc = Osc::new(&settings::Osc {
+            host: "127.0.0.1".to_string(),
+            port: default_port,
+            ..Default::default()
+        })
+        .unwrap();
+        osc.configure(
+            1,
+            &TrackOptions {
+                host: Some("localhost".to_string()),
+                address: Some(IpAddr::from([127, 0, 0, 1])),
+                port: Some(track_port),
+                prefix: Some("/synth".to_string()),
+                ..Default::default()
+            },
+        );
+
+        osc.modulation(0, 0, "amp", 1.0);
+        osc.modulation(1, 0, "amp", 1.0);
+
+        assert_eq!(
+            encode_modulation("/colly/mod", 0, "amp", 1.0),
+            receive(&default_socket)
+        );
+        assert_eq!(
+            encode_modulation("/synth/mod", 1, "amp", 1.0),
+            receive(&track_socket)
+        );
+    }
+
+    #[test]
+    fn unknown_host() {
+        assert!(Osc::new(&settings::Osc {
+            host: "unknown.invalid".to_string(),
+            ..Default::default()
+        })
+        .is_err());
+
+        let (socket, port) = listener();
+        let mut osc = Osc::new(&settings::Osc {
+            host: "127.0.0.1".to_string(),
+            port,
+            ..Default::default()
+        })
+        .unwrap();
+        // the track with the unknown host is silent
+        osc.configure(
+            1,
+            &TrackOptions {
+                host: Some("unknown.invalid".to_string()),
+                ..Default::default()
+            },
+        );
+        osc.modulation(1, 0, "amp", 1.0);
+        osc.modulation(0, 0, "amp", 0.5);
+        assert_eq!(
+            encode_modulation("/colly/mod", 0, "amp", 0.5),
+            receive(&socket)
+        );
+
+        osc.configure(1, &TrackOptions::default());
+        osc.modulation(1, 0, "amp", 1.0);
+        assert_eq!(
+            encode_modulation("/colly/mod", 1, "amp", 1.0),
+            receive(&socket)
+        );
+    }
+}
@@ -23,7 +23,7 @@ impl CollyParser {
     pub fn rule_error<T>(pair: &Pair<'_, Rule>) -> ParseResult<T> {
         Err(CollyParser::error(
             &format!("Error parsing {:?}", pair.as_rule()),
-            &pair,
+            pair,
         ))
     }
 
@@ -34,7 +34,7 @@ impl CollyParser {
         if pair.as_rule() == expected {
             Ok(())
         } else {
-            CollyParser::rule_error(&pair)
+            CollyParser::rule_error(pair)
         }
     }
 
@@ -57,7 +57,7 @@ impl CollyParser {
         previous: &Pair<'a, Rule>,
     ) -> ParseResult<Pair<'a, Rule>> {
         pairs.next().ok_or_else(|| {
-            CollyParser::error("Cannot get next pair.", &previous)
+            CollyParser::error("Cannot get next pair.", previous)
         })
     }
 
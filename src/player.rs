@@ -0,0 +1,1070 @@
+//! Real-time playback.
+//!
+//! The [Player](struct.Player.html) runs a scheduler thread, which ticks
+//! its own [Clock](../clock/struct.Clock.html) and pulls events from every
+//! scheduled pattern on each tick. The events are sent to the
+//! [Output](../output/trait.Output.html) the pattern's track is routed to.
+//! The thread is controlled by sending [Command](enum.Command.html)s.
+
+use crate::clock::{Bpm, Clock, CursorPosition, Quantisation, TempoMap};
+use crate::output::{Output, TrackOptions, DEFAULT_OUTPUT};
+use crate::types::{
+    pattern::{EventState, Value},
+    Mixer, Pattern,
+};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{
+    mpsc::{self, Receiver, RecvTimeoutError, Sender},
+    Arc, Mutex,
+};
+use std::thread;
+use std::time::{Duration, Instant};
+
+#[allow(clippy::large_enum_variant)]
+pub enum Command {
+    Start,
+    Stop,
+    Pause,
+    Seek(CursorPosition),
+    /// The tempo is changed at the current position.
+    SetTempo(Bpm),
+    /// Replace the tempo map from the current position on. The positions
+    /// of the map are relative to the current one.
+    SetTempoMap(TempoMap),
+    SetBeatsPerBar(u64),
+    SetQuantisation(Quantisation),
+    /// While playing, patterns are replaced and removed according to
+    /// the clock's quantisation.
+    SetPattern {
+        track: usize,
+        slot: usize,
+        pattern: Pattern,
+    },
+    RemovePattern {
+        track: usize,
+        slot: usize,
+    },
+    /// Replace the pattern, when its loop ends, so the loop isn't cut
+    /// regardless of the quantisation.
+    QueuePattern {
+        track: usize,
+        slot: usize,
+        pattern: Pattern,
+    },
+    /// Set the function, which is called with the track and the slot of
+    /// the playing patterns at the start of the last beat of each their
+    /// loop, so they can be queued for replacing in time.
+    OnLoopEnding(Box<dyn FnMut(usize, usize) + Send>),
+    AddOutput {
+        name: String,
+        output: Box<dyn Output>,
+    },
+    /// Route the track to the output with the name. Tracks without
+    /// routing and tracks routed to unknown outputs use the default one.
+    Route {
+        track: usize,
+        output: Option<String>,
+    },
+    Configure {
+        track: usize,
+        options: TrackOptions,
+    },
+    Quit,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum State {
+    Stopped,
+    Playing,
+    Paused,
+}
+
+/// Handle to the playback thread. The thread is stopped when the handle
+/// is dropped.
+#[derive(Debug)]
+pub struct Player {
+    sender: Sender<Command>,
+    position: Arc<Mutex<CursorPosition>>,
+    handle: Option<thread::JoinHandle<()>>,
+}
+
+impl Player {
+    /// The `output` is registered as the default one.
+    pub fn new(clock: Clock, output: Box<dyn Output>) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        let position = Arc::new(Mutex::new(clock.cursor().position));
+        let mut engine = Engine::new(clock, output, receiver, position.clone());
+        let handle = thread::spawn(move || engine.run());
+
+        Player {
+            sender,
+            position,
+            handle: Some(handle),
+        }
+    }
+
+    pub fn start(&self) {
+        self.send(Command::Start);
+    }
+
+    /// Stop playback and rewind to the beginning.
+    pub fn stop(&self) {
+        self.send(Command::Stop);
+    }
+
+    /// Stop playback keeping the current position.
+    pub fn pause(&self) {
+        self.send(Command::Pause);
+    }
+
+    pub fn seek(&self, position: CursorPosition) {
+        self.send(Command::Seek(position));
+    }
+
+    pub fn set_tempo(&self, tempo: Bpm) {
+        self.send(Command::SetTempo(tempo));
+    }
+
+    pub fn set_tempo_map(&self, map: TempoMap) {
+        self.send(Command::SetTempoMap(map));
+    }
+
+    pub fn set_beats_per_bar(&self, beats: u64) {
+        self.send(Command::SetBeatsPerBar(beats));
+    }
+
+    pub fn set_quantisation(&self, quantisation: Quantisation) {
+        self.send(Command::SetQuantisation(quantisation));
+    }
+
+    pub fn set_pattern(&self, track: usize, slot: usize, pattern: Pattern) {
+        self.send(Command::SetPattern {
+            track,
+            slot,
+            pattern,
+        });
+    }
+
+    pub fn remove_pattern(&self, track: usize, slot: usize) {
+        self.send(Command::RemovePattern { track, slot });
+    }
+
+    pub fn queue_pattern(&self, track: usize, slot: usize, pattern: Pattern) {
+        self.send(Command::QueuePattern {
+            track,
+            slot,
+            pattern,
+        });
+    }
+
+    /// The `callback` is called from the playback thread.
+    pub fn on_loop_ending<F>(&self, callback: F)
+    where
+        F: FnMut(usize, usize) + Send + 'static,
+    {
+        self.send(Command::OnLoopEnding(Box::new(callback)));
+    }
+
+    pub fn add_output(&self, name: &str, output: Box<dyn Output>) {
+        self.send(Command::AddOutput {
+            name: name.to_string(),
+            output,
+        });
+    }
+
+    pub fn route(&self, track: usize, output: Option<String>) {
+        self.send(Command::Route { track, output });
+    }
+
+    pub fn configure(&self, track: usize, options: TrackOptions) {
+        self.send(Command::Configure { track, options });
+    }
+
+    /// Send routing, output options and the playing patterns of all
+    /// the `mixer`'s tracks.
+    pub fn load(&self, mixer: &Mixer) {
+        self.set_beats_per_bar(mixer.clock.beats_per_bar());
+        self.set_quantisation(mixer.clock.quantisation());
+        for (index, track) in mixer.tracks() {
+            self.route(index, track.output().map(String::from));
+            self.configure(index, track.options().clone());
+            if let Some(slot_index) = track.playing() {
+                if let Some(slot) = track.slot(slot_index) {
+                    self.set_pattern(index, slot_index, slot.pattern().clone());
+                }
+            }
+        }
+    }
+
+    /// The position of the player's clock cursor.
+    pub fn position(&self) -> CursorPosition {
+        *self.position.lock().unwrap()
+    }
+
+    pub fn send(&self, command: Command) {
+        // the engine lives until the player is dropped
+        let _ = self.sender.send(command);
+    }
+}
+
+impl Drop for Player {
+    fn drop(&mut self) {
+        self.send(Command::Quit);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+struct Engine {
+    clock: Clock,
+    state: State,
+    patterns: BTreeMap<(usize, usize), Pattern>,
+    outputs: HashMap<String, Box<dyn Output>>,
+    routes: HashMap<usize, String>,
+    options: HashMap<usize, TrackOptions>,
+    // (track, slot, pitch) of the notes, which are on at the moment,
+    // pitches are fractional, so they can't be kept in a set
+    sounding: Vec<(usize, usize, f64)>,
+    // patterns waiting for the positions they're applied at, `None`
+    // removes the pattern
+    pending: BTreeMap<(usize, usize), (CursorPosition, Option<Pattern>)>,
+    // the positions the patterns have been launched at, their loops are
+    // counted from them
+    launches: HashMap<(usize, usize), CursorPosition>,
+    // patterns waiting for the loop ends of the ones they replace
+    queued: BTreeMap<(usize, usize), Pattern>,
+    loop_ending: Option<Box<dyn FnMut(usize, usize) + Send>>,
+    receiver: Receiver<Command>,
+    position: Arc<Mutex<CursorPosition>>,
+    // The wall-clock time of the anchor tick and its position. Each
+    // tick's deadline is counted from the anchor along the tempo map, so
+    // sleeping inaccuracies don't accumulate.
+    anchor: Instant,
+    anchor_position: CursorPosition,
+}
+
+impl Engine {
+    fn new(
+        clock: Clock,
+        output: Box<dyn Output>,
+        receiver: Receiver<Command>,
+        position: Arc<Mutex<CursorPosition>>,
+    ) -> Self {
+        let mut outputs = HashMap::new();
+        outputs.insert(DEFAULT_OUTPUT.to_string(), output);
+
+        Engine {
+            clock,
+            state: State::Stopped,
+            patterns: BTreeMap::new(),
+            outputs,
+            routes: HashMap::new(),
+            options: HashMap::new(),
+            sounding: Vec::new(),
+            pending: BTreeMap::new(),
+            launches: HashMap::new(),
+            queued: BTreeMap::new(),
+            loop_ending: None,
+            receiver,
+            position,
+            anchor: Instant::now(),
+            anchor_position: CursorPosition::default(),
+        }
+    }
+
+    fn run(&mut self) {
+        loop {
+            let command = match self.state {
+                State::Playing => {
+                    let deadline = self.deadline();
+                    let now = Instant::now();
+                    if now >= deadline {
+                        self.tick();
+                        continue;
+                    }
+
+                    match self.receiver.recv_timeout(deadline - now) {
+                        Ok(command) => command,
+                        Err(RecvTimeoutError::Timeout) => continue,
+                        Err(RecvTimeoutError::Disconnected) => break,
+                    }
+                }
+                _ => match self.receiver.recv() {
+                    Ok(command) => command,
+                    Err(_) => break,
+                },
+            };
+
+            if !self.handle_command(command) {
+                break;
+            }
+        }
+
+        self.halt();
+    }
+
+    /// Returns `false` if the engine should quit.
+    fn handle_command(&mut self, command: Command) -> bool {
+        match command {
+            Command::Start => {
+                if self.state != State::Playing {
+                    self.state = State::Playing;
+                    self.outputs.values_mut().for_each(|o| o.start());
+                    self.reanchor();
+                }
+            }
+            Command::Stop => {
+                self.halt();
+                self.state = State::Stopped;
+                self.seek(CursorPosition::new(self.clock.resolution()));
+            }
+            Command::Pause => {
+                self.halt();
+                self.state = State::Paused;
+            }
+            Command::Seek(position) => {
+                self.apply_queued(None);
+                self.seek(position);
+                self.reanchor();
+            }
+            Command::SetTempo(tempo) => {
+                // the tempo is clamped anyway, there is nobody to warn
+                // from the playback thread
+                let _ = self.clock.set_tempo(tempo);
+                self.send_tempo_map();
+                self.reanchor();
+            }
+            Command::SetTempoMap(map) => {
+                self.clock.splice_tempo_map(&map);
+                self.send_tempo_map();
+                self.reanchor();
+            }
+            Command::SetBeatsPerBar(beats) => {
+                self.clock.set_beats_per_bar(beats)
+            }
+            Command::SetQuantisation(quantisation) => {
+                self.clock.set_quantisation(quantisation)
+            }
+            Command::SetPattern {
+                track,
+                slot,
+                pattern,
+            } => self.replace_pattern(track, slot, Some(pattern)),
+            Command::RemovePattern { track, slot } => {
+                self.replace_pattern(track, slot, None)
+            }
+            Command::QueuePattern {
+                track,
+                slot,
+                pattern,
+            } => {
+                let key = (track, slot);
+                if self.state == State::Playing
+                    && self.patterns.contains_key(&key)
+                {
+                    self.queued.insert(key, pattern);
+                } else {
+                    self.swap_pattern(track, slot, Some(pattern));
+                }
+            }
+            Command::OnLoopEnding(callback) => {
+                self.loop_ending = Some(callback)
+            }
+            Command::AddOutput { name, mut output } => {
+                output.tempo_map(self.clock.tempo_map());
+                for (track, options) in self.options.iter() {
+                    output.configure(*track, options);
+                }
+                self.outputs.insert(name, output);
+            }
+            Command::Route { track, output } => {
+                self.release(|t, _| t == track);
+                match output {
+                    Some(name) => self.routes.insert(track, name),
+                    None => self.routes.remove(&track),
+                };
+            }
+            Command::Configure { track, options } => {
+                for output in self.outputs.values_mut() {
+                    output.configure(track, &options);
+                }
+                self.options.insert(track, options);
+            }
+            Command::Quit => return false,
+        }
+
+        true
+    }
+
+    fn send_tempo_map(&mut self) {
+        for output in self.outputs.values_mut() {
+            output.tempo_map(self.clock.tempo_map());
+        }
+    }
+
+    fn reanchor(&mut self) {
+        self.anchor = Instant::now();
+        self.anchor_position = self.clock.cursor().position;
+    }
+
+    // The time of the tick at the cursor's position.
+    fn deadline(&self) -> Instant {
+        let seconds = self.clock.seconds(self.clock.cursor().position)
+            - self.clock.seconds(self.anchor_position);
+        self.anchor + Duration::from_secs_f64(seconds.max(0.0))
+    }
+
+    fn replace_pattern(
+        &mut self,
+        track: usize,
+        slot: usize,
+        pattern: Option<Pattern>,
+    ) {
+        // the pattern replaces the queued one too
+        self.queued.remove(&(track, slot));
+        if self.state == State::Playing {
+            let position = self.clock.quantise(self.clock.cursor().position);
+            self.pending.insert((track, slot), (position, pattern));
+        } else {
+            self.pending.remove(&(track, slot));
+            self.swap_pattern(track, slot, pattern);
+        }
+    }
+
+    /// Apply the pending patterns, which are due at `position` or
+    /// earlier. All of them are applied if it's `None`.
+    fn apply_pending(&mut self, position: Option<CursorPosition>) {
+        let (due, pending) = std::mem::take(&mut self.pending)
+            .into_iter()
+            .partition(|(_, (at, _))| position.is_none_or(|p| *at <= p));
+        self.pending = pending;
+        for ((track, slot), (_, pattern)) in due.into_iter() {
+            self.swap_pattern(track, slot, pattern);
+        }
+    }
+
+    /// Apply the queued patterns, which replace the patterns ending their
+    /// loops at `position`. All of them are applied if it's `None`.
+    fn apply_queued(&mut self, position: Option<CursorPosition>) {
+        let due: Vec<(usize, usize)> = self
+            .queued
+            .keys()
+            .filter(|key| match (position, self.patterns.get(key)) {
+                // the previous tick is the last one of the loop
+                (Some(position), Some(pattern)) => {
+                    position.as_ticks() > 0
+                        && self.loop_remainder(key, pattern, position - 1)
+                            == Some(1)
+                }
+                _ => true,
+            })
+            .cloned()
+            .collect();
+
+        for (track, slot) in due.into_iter() {
+            if let Some(mut pattern) = self.queued.remove(&(track, slot)) {
+                // the next loops start from the loop start
+                let start = match position {
+                    Some(_) => pattern.loop_points().0.as_ticks(),
+                    None => 0,
+                };
+                for _ in 0..start {
+                    pattern.next();
+                }
+                self.swap_pattern(track, slot, Some(pattern));
+                if let Some(launch) = self.launches.get_mut(&(track, slot)) {
+                    *launch -= start;
+                }
+            }
+        }
+    }
+
+    fn swap_pattern(
+        &mut self,
+        track: usize,
+        slot: usize,
+        pattern: Option<Pattern>,
+    ) {
+        self.release(|t, s| t == track && s == slot);
+        let position = self.clock.cursor().position;
+        match pattern {
+            Some(pattern) => {
+                self.launches.insert(
+                    (track, slot),
+                    position.max(pattern.start_position()),
+                );
+                self.patterns.insert((track, slot), pattern)
+            }
+            None => {
+                self.launches.remove(&(track, slot));
+                self.patterns.remove(&(track, slot))
+            }
+        };
+    }
+
+    // The ticks from `position` to the end of the pattern's current loop.
+    fn loop_remainder(
+        &self,
+        key: &(usize, usize),
+        pattern: &Pattern,
+        position: CursorPosition,
+    ) -> Option<u64> {
+        let launch = self.launches.get(key).cloned()?;
+        let (start, end) = pattern.loop_points();
+        let (start, end) = (start.as_ticks(), end.as_ticks());
+        if position < launch || end <= start {
+            return None;
+        }
+
+        let passed = (position - launch).as_ticks();
+        Some(if passed < end {
+            end - passed
+        } else {
+            (end - start) - (passed - end) % (end - start)
+        })
+    }
+
+    // Call the loop ending callback for the patterns, which last beat of
+    // the loop, or the whole loop if it's shorter, starts at `position`.
+    fn notify_loop_endings(&mut self, position: CursorPosition) {
+        let resolution = self.clock.resolution();
+        let ending: Vec<(usize, usize)> = self
+            .patterns
+            .iter()
+            .filter(|(key, pattern)| {
+                let (start, end) = pattern.loop_points();
+                let lead = (end - start).as_ticks().min(resolution);
+                self.loop_remainder(key, pattern, position) == Some(lead)
+            })
+            .map(|(key, _)| *key)
+            .collect();
+
+        if let Some(callback) = self.loop_ending.as_mut() {
+            for (track, slot) in ending.into_iter() {
+                callback(track, slot);
+            }
+        }
+    }
+
+    fn tick(&mut self) {
+        let position = self.clock.cursor().position;
+        self.outputs.values_mut().for_each(|o| o.tick(position));
+
+        self.apply_pending(Some(position));
+        self.apply_queued(Some(position));
+        self.notify_loop_endings(position);
+
+        let is_soloed = self.options.values().any(|options| options.solo);
+        for ((track, slot), pattern) in self.patterns.iter_mut() {
+            if position < pattern.start_position() {
+                continue;
+            }
+            let is_audible =
+                self.options.get(track).map_or(!is_soloed, |options| {
+                    if is_soloed {
+                        options.solo
+                    } else {
+                        !options.mute
+                    }
+                });
+
+            let events = match pattern.next() {
+                Some(events) => events,
+                None => continue,
+            };
+            let output =
+                match Engine::output(&mut self.outputs, &self.routes, *track) {
+                    Some(output) => output,
+                    None => continue,
+                };
+
+            for event in events.iter() {
+                if let Value::Pitch(pitch, _) = event.value() {
+                    let note = (*track, *slot, *pitch);
+                    let is_sounding = self.sounding.contains(&note);
+                    self.sounding.retain(|sounding| *sounding != note);
+                    match event.state() {
+                        EventState::On if is_audible => {
+                            self.sounding.push(note)
+                        }
+                        // the notes started before muting are released, and
+                        // the ones, which haven't been started, aren't
+                        EventState::Off if is_sounding => (),
+                        _ => continue,
+                    }
+                } else if !is_audible {
+                    continue;
+                }
+                output.event(*track, *slot, event);
+            }
+        }
+
+        self.clock.cursor_mut().next();
+        self.update_position();
+    }
+
+    fn output<'a>(
+        outputs: &'a mut HashMap<String, Box<dyn Output>>,
+        routes: &HashMap<usize, String>,
+        track: usize,
+    ) -> Option<&'a mut Box<dyn Output>> {
+        let name = match routes.get(&track) {
+            Some(name) if outputs.contains_key(name) => name.as_str(),
+            _ => DEFAULT_OUTPUT,
+        };
+        outputs.get_mut(name)
+    }
+
+    /// Send note offs for the sounding notes of the matching track and slot.
+    fn release<F>(&mut self, predicate: F)
+    where
+        F: Fn(usize, usize) -> bool,
+    {
+        let (released, sounding): (Vec<_>, Vec<_>) = self
+            .sounding
+            .drain(..)
+            .partition(|(track, slot, _)| predicate(*track, *slot));
+        self.sounding = sounding;
+
+        for (track, slot, pitch) in released.into_iter() {
+            if let Some(output) =
+                Engine::output(&mut self.outputs, &self.routes, track)
+            {
+                output.note_off(track, slot, pitch);
+            }
+        }
+    }
+
+    /// Release all the notes, apply the pending patterns and stop
+    /// the outputs.
+    fn halt(&mut self) {
+        self.release(|_, _| true);
+        self.apply_pending(None);
+        self.apply_queued(None);
+        if self.state == State::Playing {
+            for output in self.outputs.values_mut() {
+                output.stop();
+                // there is nobody to report to from the playback thread
+                let _ = output.flush();
+            }
+        }
+    }
+
+    /// Move the cursor to `position`, patterns are moved accordingly.
+    fn seek(&mut self, position: CursorPosition) {
+        self.release(|_, _| true);
+        self.clock.cursor_mut().position = position;
+        for (key, pattern) in self.patterns.iter_mut() {
+            self.launches.insert(*key, pattern.start_position());
+            pattern.reset();
+            if position > pattern.start_position() {
+                let offset = (position - pattern.start_position()).as_ticks();
+                for _ in 0..offset {
+                    pattern.next();
+                }
+            }
+        }
+        self.update_position();
+    }
+
+    fn update_position(&self) {
+        *self.position.lock().unwrap() = self.clock.cursor().position;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::Cursor;
+    use crate::output::{Message, Recorder};
+    use crate::settings;
+    use crate::types::pattern::DEFAULT_VELOCITY;
+    use std::time::Duration;
+
+    fn pattern(resolution: u64) -> Pattern {
+        let mut pattern = Pattern::new(Cursor::new(resolution));
+        pattern.schedule_degree(
+            0.into(),
+            (0, 0, resolution).into(),
+            (0, 2, resolution).into(),
+        );
+        pattern.set_loop(true);
+        pattern
+    }
+
+    fn engine(resolution: u64, recorder: &Recorder) -> Engine {
+        let (_, receiver) = mpsc::channel();
+        let clock = Clock::new(
+            Bpm::default(),
+            &settings::Clock {
+                resolution,
+                ..Default::default()
+            },
+        );
+        Engine::new(
+            clock,
+            Box::new(recorder.clone()),
+            receiver,
+            Arc::new(Mutex::new(CursorPosition::new(resolution))),
+        )
+    }
+
+    fn messages(recorder: &Recorder) -> Vec<Message> {
+        recorder.messages().into_iter().map(|(_, m)| m).collect()
+    }
+
+    fn note_on(track: usize, slot: usize, resolution: u64) -> Message {
+        Message::NoteOn {
+            track,
+            slot,
+            pitch: 60.0,
+            velocity: DEFAULT_VELOCITY,
+            duration: (0, 2, resolution).into(),
+        }
+    }
+
+    fn note_off(track: usize, slot: usize) -> Message {
+        Message::NoteOff {
+            track,
+            slot,
+            pitch: 60.0,
+        }
+    }
+
+    #[test]
+    fn engine_tick() {
+        let resolution = 2;
+        let recorder = Recorder::default();
+        let mut engine = engine(resolution, &recorder);
+        engine.handle_command(Command::SetPattern {
+            track: 1,
+            slot: 3,
+            pattern: pattern(resolution),
+        });
+
+        for _ in 0..4 {
+            engine.tick();
+        }
+
+        let position = |tick| CursorPosition::from_ticks(tick, resolution);
+        assert_eq!(
+            vec![
+                (position(0), note_on(1, 3, resolution)),
+                (position(1), note_off(1, 3)),
+                (position(2), note_on(1, 3, resolution)),
+                (position(3), note_off(1, 3)),
+            ],
+            recorder.messages()
+        );
+        assert_eq!(position(4), *engine.position.lock().unwrap());
+    }
+
+    #[test]
+    fn engine_replace_at_beat() {
+        let resolution = 2;
+        let recorder = Recorder::default();
+        let mut engine = engine(resolution, &recorder);
+        engine.handle_command(Command::Start);
+        engine.tick();
+
+        engine.handle_command(Command::SetPattern {
+            track: 0,
+            slot: 0,
+            pattern: pattern(resolution),
+        });
+        engine.tick();
+        assert!(engine.patterns.is_empty());
+
+        engine.tick();
+        engine.handle_command(Command::RemovePattern { track: 0, slot: 0 });
+        engine.tick();
+        engine.tick();
+
+        let position = |tick| CursorPosition::from_ticks(tick, resolution);
+        assert_eq!(
+            vec![
+                (CursorPosition::default(), Message::Start),
+                (position(2), note_on(0, 0, resolution)),
+                (position(3), note_off(0, 0)),
+            ],
+            recorder.messages()
+        );
+        assert!(engine.patterns.is_empty());
+    }
+
+    #[test]
+    fn engine_replace_at_bar() {
+        let resolution = 2;
+        let recorder = Recorder::default();
+        let mut engine = engine(resolution, &recorder);
+        engine.handle_command(Command::SetBeatsPerBar(3));
+        engine.handle_command(Command::SetQuantisation(Quantisation::Bar));
+        engine.handle_command(Command::Start);
+        engine.tick();
+        engine.tick();
+        engine.tick();
+
+        engine.handle_command(Command::SetPattern {
+            track: 0,
+            slot: 0,
+            pattern: pattern(resolution),
+        });
+        for _ in 0..3 {
+            engine.tick();
+            assert!(engine.patterns.is_empty());
+        }
+        engine.tick();
+
+        assert_eq!(
+            vec![
+                (CursorPosition::default(), Message::Start),
+                ((3, 0, resolution).into(), note_on(0, 0, resolution)),
+            ],
+            recorder.messages()
+        );
+    }
+
+    #[test]
+    fn engine_queue_at_loop_end() {
+        let resolution = 2;
+        let recorder = Recorder::default();
+        let mut engine = engine(resolution, &recorder);
+        engine.handle_command(Command::Start);
+        engine.tick();
+        engine.handle_command(Command::SetPattern {
+            track: 0,
+            slot: 0,
+            pattern: pattern(resolution),
+        });
+        engine.tick();
+        engine.tick();
+
+        // the loop launched at the second beat ends before the bar
+        engine.handle_command(Command::SetQuantisation(Quantisation::Bar));
+        let mut queued = Pattern::new(Cursor::new(resolution));
+        queued.schedule_degree(
+            2.into(),
+            (0, 0, resolution).into(),
+            (0, 2, resolution).into(),
+        );
+        queued.set_loop(true);
+        engine.handle_command(Command::QueuePattern {
+            track: 0,
+            slot: 0,
+            pattern: queued,
+        });
+        engine.tick();
+        assert!(!engine.queued.is_empty());
+        engine.tick();
+
+        let position = |tick| CursorPosition::from_ticks(tick, resolution);
+        assert_eq!(
+            vec![
+                (CursorPosition::default(), Message::Start),
+                (position(2), note_on(0, 0, resolution)),
+                (position(3), note_off(0, 0)),
+                (
+                    position(4),
+                    Message::NoteOn {
+                        track: 0,
+                        slot: 0,
+                        pitch: 62.0,
+                        velocity: DEFAULT_VELOCITY,
+                        duration: (0, 2, resolution).into(),
+                    }
+                ),
+            ],
+            recorder.messages()
+        );
+        assert!(engine.queued.is_empty());
+    }
+
+    #[test]
+    fn engine_loop_ending() {
+        let resolution = 2;
+        let recorder = Recorder::default();
+        let mut engine = engine(resolution, &recorder);
+        let endings = Arc::new(Mutex::new(Vec::new()));
+        let sender = endings.clone();
+        engine.handle_command(Command::OnLoopEnding(Box::new(
+            move |track, slot| sender.lock().unwrap().push((track, slot)),
+        )));
+
+        // a two beats long loop launched at the second beat
+        let mut pattern = pattern(resolution);
+        pattern.set_length(Some((2, 0, resolution).into()));
+        engine.handle_command(Command::Start);
+        engine.tick();
+        engine.handle_command(Command::SetPattern {
+            track: 1,
+            slot: 2,
+            pattern,
+        });
+        let mut ticks = Vec::new();
+        for tick in 1..10 {
+            let count = endings.lock().unwrap().len();
+            engine.tick();
+            if endings.lock().unwrap().len() > count {
+                ticks.push(tick);
+            }
+        }
+
+        assert_eq!(vec![4, 8], ticks);
+        assert_eq!(vec![(1, 2), (1, 2)], *endings.lock().unwrap());
+    }
+
+    #[test]
+    fn engine_routing() {
+        let resolution = 2;
+        let default = Recorder::default();
+        let routed = Recorder::default();
+        let mut engine = engine(resolution, &default);
+        engine.handle_command(Command::AddOutput {
+            name: "routed".to_string(),
+            output: Box::new(routed.clone()),
+        });
+        engine.handle_command(Command::Route {
+            track: 1,
+            output: Some("routed".to_string()),
+        });
+        for track in 0..2 {
+            engine.handle_command(Command::SetPattern {
+                track,
+                slot: 0,
+                pattern: pattern(resolution),
+            });
+        }
+
+        engine.tick();
+
+        assert_eq!(vec![note_on(0, 0, resolution)], messages(&default));
+        assert_eq!(vec![note_on(1, 0, resolution)], messages(&routed));
+
+        // rerouting releases the sounding notes
+        engine.handle_command(Command::Route {
+            track: 1,
+            output: None,
+        });
+        assert_eq!(
+            vec![note_on(1, 0, resolution), note_off(1, 0)],
+            messages(&routed)
+        );
+    }
+
+    #[test]
+    fn engine_mute_and_solo() {
+        let resolution = 2;
+        let recorder = Recorder::default();
+        let mut engine = engine(resolution, &recorder);
+        for track in 0..3 {
+            engine.handle_command(Command::SetPattern {
+                track,
+                slot: 0,
+                pattern: pattern(resolution),
+            });
+        }
+        let configure = |mute, solo| TrackOptions {
+            mute,
+            solo,
+            ..Default::default()
+        };
+
+        engine.handle_command(Command::Configure {
+            track: 1,
+            options: configure(true, false),
+        });
+        engine.tick();
+        let mut notes: Vec<usize> = messages(&recorder)
+            .into_iter()
+            .filter_map(|message| match message {
+                Message::NoteOn { track, .. } => Some(track),
+                _ => None,
+            })
+            .collect();
+        notes.sort_unstable();
+        assert_eq!(vec![0, 2], notes);
+
+        // the sounding notes of the tracks, which aren't soloed, are
+        // released anyway
+        engine.handle_command(Command::Configure {
+            track: 2,
+            options: configure(false, true),
+        });
+        engine.tick();
+        engine.tick();
+        let mut messages: Vec<Message> =
+            messages(&recorder).into_iter().skip(2).collect();
+        messages.sort_by_key(|message| message.to_string());
+        assert_eq!(
+            vec![note_off(0, 0), note_off(2, 0), note_on(2, 0, resolution)],
+            messages
+        );
+    }
+
+    #[test]
+    fn engine_seek_and_stop() {
+        let resolution = 2;
+        let recorder = Recorder::default();
+        let mut engine = engine(resolution, &recorder);
+        engine.handle_command(Command::SetPattern {
+            track: 0,
+            slot: 0,
+            pattern: pattern(resolution),
+        });
+        engine.handle_command(Command::Start);
+        engine.tick();
+
+        // the sounding note is released and the end of the note, which
+        // isn't started, isn't sent
+        engine.handle_command(Command::Seek((3, 1, resolution).into()));
+        engine.tick();
+        engine.handle_command(Command::Stop);
+
+        assert_eq!(
+            vec![
+                Message::Start,
+                note_on(0, 0, resolution),
+                note_off(0, 0),
+                Message::Stop
+            ],
+            messages(&recorder)
+        );
+        assert_eq!(State::Stopped, engine.state);
+        assert_eq!(
+            CursorPosition::new(resolution),
+            engine.clock.cursor().position
+        );
+    }
+
+    #[test]
+    fn player_plays_in_real_time() {
+        let resolution = 24;
+        let recorder = Recorder::default();
+        let clock = Clock::new(
+            Bpm::new(200.0).unwrap(),
+            &settings::Clock {
+                resolution,
+                ..Default::default()
+            },
+        );
+        let tick_interval = clock.tick_interval();
+        let player = Player::new(clock, Box::new(recorder.clone()));
+
+        player.set_pattern(0, 0, pattern(resolution));
+        player.start();
+        thread::sleep(tick_interval * 10);
+        player.pause();
+        thread::sleep(Duration::from_millis(20));
+
+        let position = player.position();
+        assert!(position.as_ticks() > 0);
+        assert_eq!(
+            Some(&(CursorPosition::new(resolution), note_on(0, 0, resolution))),
+            recorder.messages().get(1)
+        );
+
+        thread::sleep(tick_interval * 4);
+        assert_eq!(position, player.position());
+    }
+}
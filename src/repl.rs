@@ -0,0 +1,263 @@
+//! Read-eval-print loop.
+
+use crate::live::Session;
+use crate::output::Logger;
+use crate::player::Player;
+use crate::types::{Mixer, Value};
+use std::io::{self, BufRead, Write};
+use std::sync::mpsc;
+use std::thread;
+
+const PROMPT: &str = ">> ";
+const CONTINUATION_PROMPT: &str = ".. ";
+
+/// The result of feeding a line to the [Repl](struct.Repl.html).
+#[derive(Debug)]
+pub enum Evaluation {
+    /// The input has unclosed brackets, so more lines are expected.
+    Incomplete,
+    /// The values of the evaluated statements.
+    Values(Vec<Value>),
+    /// A parsing or interpreting error message.
+    Error(String),
+}
+
+// The inputs of the loop: the lines read and the loop endings of the
+// patterns played.
+enum Input {
+    Line(io::Result<String>),
+    End,
+    LoopEnding(usize, usize),
+}
+
+/// Evaluates the input line by line keeping the
+/// [Session](../live/struct.Session.html) between the lines, so the
+/// patterns are played and their inputs are updated at their loop ends.
+/// A statement continues on the next lines while it has unclosed `(`,
+/// `[` or `{`.
+pub struct Repl<'a> {
+    session: Session<'a>,
+    buffer: String,
+}
+
+impl<'a> Default for Repl<'a> {
+    /// The patterns are played silently.
+    fn default() -> Self {
+        let mixer = Mixer::default();
+        let player =
+            Player::new(mixer.clock.clone(), Box::new(Logger::new(io::sink())));
+        Repl::with_session(Session::new(mixer, player))
+    }
+}
+
+impl<'a> Repl<'a> {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn with_session(session: Session<'a>) -> Self {
+        Repl {
+            session,
+            buffer: String::new(),
+        }
+    }
+
+    pub fn prompt(&self) -> &'static str {
+        if self.buffer.is_empty() {
+            PROMPT
+        } else {
+            CONTINUATION_PROMPT
+        }
+    }
+
+    pub fn feed(&mut self, line: &str) -> Evaluation {
+        if self.buffer.is_empty() && line.trim().is_empty() {
+            return Evaluation::Values(Vec::new());
+        }
+
+        self.buffer
+            .push_str(line.trim_end_matches(&['\r', '\n'][..]));
+        if unclosed_brackets(&self.buffer) > 0 {
+            self.buffer.push('\n');
+            return Evaluation::Incomplete;
+        }
+
+        let source = std::mem::take(&mut self.buffer);
+        match self.session.interpret(&source) {
+            Ok(values) => {
+                self.session.player().start();
+                Evaluation::Values(values)
+            }
+            Err(error) => Evaluation::Error(error.to_string()),
+        }
+    }
+
+    /// Run the loop until the end of the `input`. The input is read on
+    /// another thread, so the inputs of the patterns are updated while
+    /// waiting for the lines.
+    pub fn run<R, W>(&mut self, input: R, output: &mut W) -> io::Result<()>
+    where
+        R: BufRead + Send + 'static,
+        W: Write,
+    {
+        let (sender, receiver) = mpsc::channel();
+        let loop_ending = sender.clone();
+        self.session.player().on_loop_ending(move |track, slot| {
+            // the loop is over, if the receiver is gone
+            let _ = loop_ending.send(Input::LoopEnding(track, slot));
+        });
+        thread::spawn(move || {
+            for line in input.lines() {
+                if sender.send(Input::Line(line)).is_err() {
+                    return;
+                }
+            }
+            let _ = sender.send(Input::End);
+        });
+
+        write!(output, "{}", self.prompt())?;
+        output.flush()?;
+
+        for input in receiver.iter() {
+            match input {
+                Input::Line(line) => match self.feed(&line?) {
+                    Evaluation::Incomplete => (),
+                    Evaluation::Values(values) => {
+                        for value in values.iter().filter_map(describe) {
+                            writeln!(output, "{}", value)?;
+                        }
+                    }
+                    Evaluation::Error(message) => {
+                        writeln!(output, "Error: {}", message)?
+                    }
+                },
+                Input::End => break,
+                Input::LoopEnding(track, slot) => {
+                    match self.session.update_inputs(track, slot) {
+                        Ok(()) => continue,
+                        Err(error) => writeln!(output, "\nError: {}", error)?,
+                    }
+                }
+            }
+
+            for warning in
+                self.session.context_mut().mixer_mut().take_warnings()
+            {
+                writeln!(output, "Warning: {}", warning)?;
+            }
+
+            write!(output, "{}", self.prompt())?;
+            output.flush()?;
+        }
+
+        writeln!(output)
+    }
+}
+
+/// Describe the value for printing. Literal values are followed by
+/// their type. `Void` values have no description.
+pub fn describe(value: &Value) -> Option<String> {
+    let type_id = value.type_id().to_string();
+    match value {
+        Value::Void(_) => None,
+        Value::Identifier(_)
+        | Value::Boolean(_)
+        | Value::Number(_)
+        | Value::String(_)
+        | Value::Properties(_)
+        | Value::Array(_) => Some(format!("{} {}", value, type_id)),
+        _ => Some(type_id),
+    }
+}
+
+/// Count brackets, which are opened but not closed. Brackets in strings
+/// and comments are skipped.
+fn unclosed_brackets(source: &str) -> i64 {
+    let mut result = 0;
+    let mut chars = source.chars();
+    let mut is_string = false;
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if is_string => {
+                chars.next();
+            }
+            '"' => is_string = !is_string,
+            _ if is_string => (),
+            '#' => {
+                for c in chars.by_ref() {
+                    if c == '\n' {
+                        break;
+                    }
+                }
+            }
+            '(' | '[' | '{' => result += 1,
+            ')' | ']' | '}' => result -= 1,
+            _ => (),
+        }
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn brackets() {
+        assert_eq!(0, unclosed_brackets("$1@0 | 0 [1 2] (3 4) |"));
+        assert_eq!(1, unclosed_brackets("$1 {output: \"}\""));
+        assert_eq!(2, unclosed_brackets("[{ # }]"));
+        assert_eq!(-1, unclosed_brackets(")"));
+    }
+
+    #[test]
+    fn run_lines() {
+        let input =
+            ":x = 42\n:x\n$1 {\noutput: \"log\"\n}\n:s = \"a\"\n$1\n:s {\n";
+        let mut output = Vec::new();
+        Repl::new().run(input.as_bytes(), &mut output).unwrap();
+
+        assert_eq!(
+            ">> >> 42 <Number>\n>> .. .. >> >> <Track>\n>> .. \n",
+            String::from_utf8(output).unwrap()
+        );
+    }
+
+    #[test]
+    fn report_errors() {
+        let mut repl = Repl::new();
+
+        match repl.feed(":x = ") {
+            Evaluation::Error(message) => {
+                assert!(message.contains("1:6"));
+                assert!(message.contains('^'));
+            }
+            evaluation => panic!("unexpected evaluation {:?}", evaluation),
+        }
+
+        match repl.feed(":x = 1\n:x {a: 1}") {
+            Evaluation::Error(message) => assert_eq!(
+                "Cannot set property a for <Number>: the value has no \
+                 properties",
+                message
+            ),
+            evaluation => panic!("unexpected evaluation {:?}", evaluation),
+        }
+    }
+
+    #[test]
+    fn report_warnings() {
+        let mut output = Vec::new();
+        Repl::new()
+            .run("$ {tempo: 500}\n".as_bytes(), &mut output)
+            .unwrap();
+
+        assert_eq!(
+            ">> Warning: Tempo 500 is out of the limits, 200 is used \
+             instead\n>> \n",
+            String::from_utf8(output).unwrap()
+        );
+    }
+}
@@ -1,7 +1,10 @@
 //! Application's settings.
 
+use crate::clock::Quantisation;
+use crate::types::{Groove, Scale};
 use config::{Config, Environment};
 use serde::Deserialize;
+use std::collections::HashMap;
 
 const ENV_VAR_PREFIX: &str = "COLLY";
 
@@ -9,6 +12,14 @@ const ENV_VAR_PREFIX: &str = "COLLY";
 #[serde(default)]
 pub struct Settings {
     pub clock: Clock,
+    pub osc: Osc,
+    pub midi_file: MidiFile,
+    /// Custom scales, which are added to the built-in ones.
+    pub scales: HashMap<String, Scale>,
+    /// Paths to Scala `.scl` files by the names of the tunings.
+    pub tunings: HashMap<String, String>,
+    /// Grooves by their names.
+    pub grooves: HashMap<String, Groove>,
 }
 
 impl Settings {
@@ -29,11 +40,68 @@ impl Settings {
 #[serde(default)]
 pub struct Clock {
     pub resolution: u64,
+    /// The initial tempo in beats per minute.
+    pub tempo: f64,
+    /// The limits of the tempo. Tempos out of them are clamped.
+    pub min_tempo: f64,
+    pub max_tempo: f64,
+    /// The number of the last intervals between taps, which are averaged
+    /// by the tap tempo.
+    pub tap_tempo_taps: usize,
+    /// The time signature as the number of beats in a bar.
+    pub beats_per_bar: u64,
+    /// When pattern changes take effect: `tick`, `beat`, `bar` or
+    /// `<n> bars`.
+    pub quantisation: Quantisation,
 }
 
 impl Default for Clock {
     fn default() -> Self {
-        Clock { resolution: 1920 }
+        Clock {
+            resolution: 1920,
+            tempo: 117.0,
+            min_tempo: 27.0,
+            max_tempo: 200.0,
+            tap_tempo_taps: 4,
+            beats_per_bar: 4,
+            quantisation: Quantisation::default(),
+        }
+    }
+}
+
+/// The default destination of the [OSC output](../output/struct.Osc.html).
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct Osc {
+    pub host: String,
+    pub port: u16,
+    /// The address prefix of the messages, i.e. `/colly` for `/colly/note`.
+    pub prefix: String,
+}
+
+impl Default for Osc {
+    fn default() -> Self {
+        Osc {
+            host: "127.0.0.1".to_string(),
+            // sclang
+            port: 57120,
+            prefix: "/colly".to_string(),
+        }
+    }
+}
+
+/// The file of the [MIDI file output](../output/struct.MidiFileWriter.html).
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct MidiFile {
+    pub path: String,
+}
+
+impl Default for MidiFile {
+    fn default() -> Self {
+        MidiFile {
+            path: "colly.mid".to_string(),
+        }
     }
 }
 
@@ -51,4 +119,81 @@ mod tests {
 
         assert_eq!(12, settings.clock.resolution,);
     }
+
+    #[test]
+    fn merge_osc() {
+        let file = config::File::from_str(
+            "[osc]\nport = 9000",
+            config::FileFormat::Toml,
+        );
+        let settings = Settings::new(file).unwrap();
+
+        assert_eq!(9000, settings.osc.port);
+        assert_eq!("/colly", settings.osc.prefix);
+    }
+
+    #[test]
+    fn merge_midi_file() {
+        let file = config::File::from_str(
+            "[midi_file]\npath = \"take.mid\"",
+            config::FileFormat::Toml,
+        );
+        let settings = Settings::new(file).unwrap();
+
+        assert_eq!("take.mid", settings.midi_file.path);
+    }
+
+    #[test]
+    fn merge_scales() {
+        let file = config::File::from_str(
+            "[scales.slendro]\nname = \"Slendro\"\npitch_set = [0, 2, 5, 7, 9]",
+            config::FileFormat::Toml,
+        );
+        let settings = Settings::new(file).unwrap();
+
+        assert_eq!(
+            Some(&Scale::new("Slendro", &[0, 2, 5, 7, 9])),
+            settings.scales.get("slendro")
+        );
+    }
+
+    #[test]
+    fn reject_empty_scales() {
+        let file = config::File::from_str(
+            "[scales.empty]\nname = \"Empty\"\npitch_set = []",
+            config::FileFormat::Toml,
+        );
+        let error = Settings::new(file).unwrap_err().to_string();
+
+        assert!(error.contains("the pitch set of scale Empty is empty"));
+    }
+
+    #[test]
+    fn merge_grooves() {
+        let file = config::File::from_str(
+            "[grooves.lazy]\nname = \"Lazy\"\nsubdivision = 4\n\
+             offsets = [0, 40, 0, 60]\nvelocities = [1.0, 0.7]",
+            config::FileFormat::Toml,
+        );
+        let settings = Settings::new(file).unwrap();
+
+        assert_eq!(
+            Some(
+                &Groove::new("Lazy", 4, &[0, 40, 0, 60], &[1.0, 0.7]).unwrap()
+            ),
+            settings.grooves.get("lazy")
+        );
+    }
+
+    #[test]
+    fn reject_invalid_grooves() {
+        let file = config::File::from_str(
+            "[grooves.still]\nname = \"Still\"\nsubdivision = 0\n\
+             offsets = [10]",
+            config::FileFormat::Toml,
+        );
+        let error = Settings::new(file).unwrap_err().to_string();
+
+        assert!(error.contains("groove Still: the subdivision should be"));
+    }
 }
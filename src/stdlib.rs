@@ -0,0 +1,515 @@
+//! Built-in functions, which are registered in every
+//! [Context](../interpreter/struct.Context.html).
+//!
+//! All of them transform patterns. The pattern is the first argument, so
+//! they're used as pattern methods, i.e. `| 0 1 2 | reverse (transpose 2)`.
+
+use crate::clock::{CursorPosition, Duration};
+use crate::interpreter;
+use crate::types::{
+    Degree, Function, Guide, Identifier, Pattern, TypeId, Value,
+};
+
+/// The largest number of times a pattern can be repeated.
+pub const MAX_REPEATS: u64 = 1024;
+/// The largest number of scale degrees a pattern can be transposed by.
+pub const MAX_TRANSPOSITION: i64 = 1024;
+
+/// Get all the built-in functions.
+pub fn functions() -> Vec<Box<dyn Function<Item = Value>>> {
+    vec![
+        Box::new(Reverse::default()),
+        Box::new(Rotate::default()),
+        Box::new(Transpose::default()),
+        Box::new(Invert::default()),
+        Box::new(Stretch::default()),
+        Box::new(Shuffle::default()),
+        Box::new(Every::default()),
+        Box::new(Degrade::default()),
+        Box::new(Palindrome::default()),
+        Box::new(Repeat::default()),
+    ]
+}
+
+macro_rules! pattern_function {
+    (
+        $name:ident,
+        $identifier:expr,
+        [$($argument:ident),*],
+        $description:expr,
+        $help:expr,
+        $transform:expr
+    ) => {
+        #[derive(Debug, Clone, Default)]
+        pub struct $name {
+            arguments: Vec<Value>,
+        }
+
+        impl Function for $name {
+            fn identifier(&self) -> Identifier {
+                Identifier($identifier.to_string())
+            }
+
+            fn arguments(&self) -> Vec<TypeId> {
+                vec![TypeId::Pattern, $(TypeId::$argument),*]
+            }
+
+            fn returns(&self) -> TypeId {
+                TypeId::Pattern
+            }
+
+            fn set_arguments(&mut self, arguments: Vec<Value>) {
+                self.arguments = arguments;
+            }
+        }
+
+        impl Guide for $name {
+            fn description(&self) -> &'static str {
+                $description
+            }
+
+            fn help(&self) -> &'static str {
+                $help
+            }
+        }
+
+        impl Iterator for $name {
+            type Item = Value;
+
+            fn next(&mut self) -> Option<Self::Item> {
+                let transform: fn(Pattern, &[Value]) -> Option<Pattern> =
+                    $transform;
+                match self.arguments.split_first() {
+                    Some((Value::Pattern(pattern), arguments)) => {
+                        transform(pattern.clone(), arguments).map(Value::from)
+                    }
+                    _ => None,
+                }
+            }
+        }
+    };
+}
+
+pattern_function!(
+    Reverse,
+    "reverse",
+    [],
+    "Play the pattern backwards.",
+    "| 0 1 2 | reverse",
+    |mut pattern, _| {
+        let length = pattern.length();
+        pattern.map_timing(|position, duration| {
+            vec![(length - (position + duration), duration)]
+        });
+        Some(pattern)
+    }
+);
+
+pattern_function!(
+    Rotate,
+    "rotate",
+    [Number],
+    "Shift the pattern to the left by the number of beats. The events, \
+     which are moved before the beginning, are wrapped to the end.",
+    "| 0 1 2 | (rotate 1)",
+    |mut pattern, arguments| {
+        let beats = number(arguments, 0)?;
+        let length = pattern.length().as_ticks() as i64;
+        if length == 0 {
+            return Some(pattern);
+        }
+
+        let resolution = pattern.resolution();
+        let shift = (beats * resolution as f64).round() as i64;
+        pattern.map_timing(|position, duration| {
+            let ticks = (position.as_ticks() as i64 - shift).rem_euclid(length);
+            vec![(
+                CursorPosition::from_ticks(ticks as u64, resolution),
+                duration,
+            )]
+        });
+        Some(pattern)
+    }
+);
+
+pattern_function!(
+    Transpose,
+    "transpose",
+    [Number],
+    "Transpose the pattern by the number of scale degrees (up to 1024 \
+     either way). Degrees below zero are clamped to zero.",
+    "| 0 1 2 | (transpose 2)",
+    |mut pattern, arguments| {
+        let steps = (number(arguments, 0)?.round() as i64)
+            .clamp(-MAX_TRANSPOSITION, MAX_TRANSPOSITION);
+        pattern.map_degrees(|degree| Degree {
+            value: (degree.value.min(i64::MAX as u64) as i64)
+                .saturating_add(steps)
+                .max(0) as u64,
+            alteration: degree.alteration,
+            accent: degree.accent,
+        });
+        Some(pattern)
+    }
+);
+
+pattern_function!(
+    Invert,
+    "invert",
+    [],
+    "Mirror the degrees of the pattern, so the highest one becomes \
+     the lowest one and vice versa.",
+    "| 0 2 4 | invert",
+    |mut pattern, _| {
+        let degrees = pattern.degrees();
+        let lowest = degrees.iter().map(|d| d.value).min()?;
+        let highest = degrees.iter().map(|d| d.value).max()?;
+        pattern.map_degrees(|degree| Degree {
+            value: lowest + highest - degree.value,
+            alteration: -degree.alteration,
+            accent: degree.accent,
+        });
+        Some(pattern)
+    }
+);
+
+pattern_function!(
+    Stretch,
+    "stretch",
+    [Number],
+    "Multiply positions and durations of the events by the factor.",
+    "| 0 1 2 | (stretch 0.5)",
+    |mut pattern, arguments| {
+        let factor = number(arguments, 0)?;
+        if factor <= 0.0 {
+            return None;
+        }
+
+        let tick = Duration::from_ticks(1, pattern.resolution());
+        pattern.map_timing(|position, duration| {
+            vec![(position * factor, (duration * factor).max(tick))]
+        });
+        pattern.stretch_length(factor);
+        Some(pattern)
+    }
+);
+
+pattern_function!(
+    Shuffle,
+    "shuffle",
+    [Number],
+    "Play the beats of the pattern in random order. The order is defined \
+     by the seed.",
+    "| 0 1 2 3 | (shuffle 42)",
+    |mut pattern, arguments| {
+        let seed = number(arguments, 0)? as u64;
+        let resolution = pattern.resolution();
+        // the events can end after the explicit length
+        let beats = pattern.length().max(pattern.events_length()).beat();
+
+        // Fisher-Yates
+        let mut order: Vec<u64> = (0..beats).collect();
+        for n in (1..order.len()).rev() {
+            let other = (random(seed, n as u64) * (n + 1) as f64) as usize;
+            order.swap(n, other.min(n));
+        }
+
+        pattern.map_timing(|position, duration| {
+            let beat = order[position.beat() as usize];
+            vec![((beat, position.tick(), resolution).into(), duration)]
+        });
+        Some(pattern)
+    }
+);
+
+pattern_function!(
+    Every,
+    "every",
+    [Number, Function],
+    "Repeat the pattern the whole number of times (from 1 to 1024) \
+     applying the function to the first repetition.",
+    "| 0 1 2 | (every 3 reverse)",
+    |pattern, arguments| {
+        let times = count(arguments, 0)?;
+        let function = match arguments.get(1) {
+            Some(Value::Function(function)) => function.clone(),
+            _ => return None,
+        };
+
+        let mut result = match interpreter::apply(
+            function,
+            vec![Value::from(pattern.clone())],
+        ) {
+            Ok(Value::Pattern(result)) => result,
+            _ => return None,
+        };
+        for _ in 1..times {
+            result.append(pattern.clone());
+        }
+        Some(result)
+    }
+);
+
+pattern_function!(
+    Degrade,
+    "degrade",
+    [Number, Number],
+    "Remove events randomly with the probability (from 0 to 1). Removed \
+     events are defined by the seed.",
+    "| 0 1 2 3 | (degrade 0.5 42)",
+    |mut pattern, arguments| {
+        let probability = number(arguments, 0)?;
+        let seed = number(arguments, 1)? as u64;
+        pattern.map_timing(|position, duration| {
+            if random(seed, position.as_ticks()) < probability {
+                Vec::new()
+            } else {
+                vec![(position, duration)]
+            }
+        });
+        Some(pattern)
+    }
+);
+
+pattern_function!(
+    Palindrome,
+    "palindrome",
+    [],
+    "Play the pattern forwards and then backwards.",
+    "| 0 1 2 | palindrome",
+    |mut pattern, _| {
+        let length = pattern.length();
+        pattern.map_timing(|position, duration| {
+            vec![
+                (position, duration),
+                (length + length - (position + duration), duration),
+            ]
+        });
+        pattern.repeat_length(2.0);
+        Some(pattern)
+    }
+);
+
+pattern_function!(
+    Repeat,
+    "repeat",
+    [Number],
+    "Repeat the pattern the whole number of times (from 1 to 1024).",
+    "| 0 1 2 | (repeat 2)",
+    |mut pattern, arguments| {
+        let times = count(arguments, 0)?;
+        let length = pattern.length();
+        pattern.map_timing(|position, duration| {
+            (0..times)
+                .map(|n| (position + length * n as f64, duration))
+                .collect()
+        });
+        pattern.repeat_length(times as f64);
+        Some(pattern)
+    }
+);
+
+fn number(arguments: &[Value], index: usize) -> Option<f64> {
+    match arguments.get(index) {
+        Some(Value::Number(value)) => Some(*value),
+        _ => None,
+    }
+}
+
+// The number of repetitions, which is a whole number from 1 to
+// `MAX_REPEATS`.
+fn count(arguments: &[Value], index: usize) -> Option<u64> {
+    let value = number(arguments, index)?;
+    if value.fract() == 0.0 && value >= 1.0 && value <= MAX_REPEATS as f64 {
+        Some(value as u64)
+    } else {
+        None
+    }
+}
+
+/// Get a pseudo-random number in range [0, 1) for the seed and the index
+/// (SplitMix64).
+fn random(seed: u64, index: u64) -> f64 {
+    let mut value = seed
+        .wrapping_add(index.wrapping_mul(0x9e37_79b9_7f4a_7c15))
+        .wrapping_add(0x9e37_79b9_7f4a_7c15);
+    value = (value ^ (value >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    value = (value ^ (value >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    value ^= value >> 31;
+    (value >> 11) as f64 / (1u64 << 53) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::Cursor;
+    use crate::types::pattern::{EventState, Value as PatternValue};
+
+    const RESOLUTION: u64 = 2;
+
+    // schedule degrees one per beat
+    fn pattern(degrees: &[u64]) -> Pattern {
+        let mut pattern = Pattern::new(Cursor::new(RESOLUTION));
+        for (beat, degree) in degrees.iter().enumerate() {
+            pattern.schedule_degree(
+                (*degree).into(),
+                (beat as u64, 0, RESOLUTION).into(),
+                (1, 0, RESOLUTION).into(),
+            );
+        }
+        pattern
+    }
+
+    fn call(function: &str, arguments: Vec<Value>) -> Pattern {
+        let function = functions()
+            .into_iter()
+            .find(|f| f.identifier().0 == function)
+            .unwrap();
+        match interpreter::apply(function, arguments).unwrap() {
+            Value::Pattern(pattern) => pattern,
+            value => panic!("unexpected value {:?}", value),
+        }
+    }
+
+    // pitches of the note ons with their ticks
+    fn notes(pattern: Pattern) -> Vec<(u64, u64)> {
+        let mut result = Vec::new();
+        for (tick, events) in pattern.enumerate() {
+            for event in events.into_iter() {
+                if let (PatternValue::Pitch(pitch, _), EventState::On) =
+                    (event.value(), event.state())
+                {
+                    result.push((tick as u64, *pitch as u64));
+                }
+            }
+        }
+        result
+    }
+
+    #[test]
+    fn reverse() {
+        let pattern = call("reverse", vec![pattern(&[0, 1, 2]).into()]);
+        assert_eq!(vec![(0, 62), (2, 61), (4, 60)], notes(pattern));
+    }
+
+    #[test]
+    fn rotate() {
+        let pattern =
+            call("rotate", vec![pattern(&[0, 1, 2]).into(), 1.0.into()]);
+        assert_eq!(vec![(0, 61), (2, 62), (4, 60)], notes(pattern));
+    }
+
+    #[test]
+    fn transpose_and_invert() {
+        let pattern =
+            call("transpose", vec![pattern(&[0, 1, 4]).into(), (-1.0).into()]);
+        assert_eq!(vec![(0, 60), (2, 60), (4, 63)], notes(pattern));
+
+        let pattern =
+            call("transpose", vec![self::pattern(&[0]).into(), 1e30.into()]);
+        assert_eq!(
+            vec![MAX_TRANSPOSITION as u64],
+            pattern
+                .degrees()
+                .iter()
+                .map(|d| d.value)
+                .collect::<Vec<u64>>()
+        );
+
+        let pattern = call("invert", vec![self::pattern(&[0, 1, 4]).into()]);
+        assert_eq!(vec![(0, 64), (2, 63), (4, 60)], notes(pattern));
+    }
+
+    #[test]
+    fn stretch() {
+        let pattern =
+            call("stretch", vec![pattern(&[0, 1]).into(), 0.5.into()]);
+        assert_eq!(vec![(0, 60), (1, 61)], notes(pattern));
+    }
+
+    #[test]
+    fn shuffle() {
+        let shuffled =
+            call("shuffle", vec![pattern(&[0, 1, 2, 3]).into(), 7.0.into()]);
+        let mut pitches: Vec<u64> = notes(shuffled.clone())
+            .into_iter()
+            .map(|(_, p)| p)
+            .collect();
+        assert_ne!(vec![60, 61, 62, 63], pitches);
+        pitches.sort();
+        assert_eq!(vec![60, 61, 62, 63], pitches);
+
+        let again =
+            call("shuffle", vec![pattern(&[0, 1, 2, 3]).into(), 7.0.into()]);
+        assert_eq!(notes(shuffled), notes(again));
+
+        // the explicit length is shorter than the events
+        for length in &[1.0, 0.5] {
+            let mut source = pattern(&[0, 1, 2]);
+            source.set_length(Some(CursorPosition::from_f64(
+                *length, RESOLUTION,
+            )));
+            let shuffled = call("shuffle", vec![source.into(), 3.0.into()]);
+            assert_eq!(3, shuffled.degrees().len());
+        }
+    }
+
+    #[test]
+    fn every() {
+        let reverse = Value::Function(Box::new(Reverse::default()));
+        let pattern =
+            call("every", vec![pattern(&[0, 1]).into(), 2.0.into(), reverse]);
+        assert_eq!(vec![(0, 61), (2, 60), (4, 60), (6, 61)], notes(pattern));
+
+        for times in &[0.0, 1.5, 1e12] {
+            let reverse = Value::Function(Box::new(Reverse::default()));
+            let arguments =
+                vec![self::pattern(&[0]).into(), (*times).into(), reverse];
+            assert!(interpreter::apply(Box::new(Every::default()), arguments)
+                .is_err());
+        }
+    }
+
+    #[test]
+    fn degrade() {
+        let source = pattern(&[0, 1, 2, 3, 4, 5, 6, 7]);
+        let all = call(
+            "degrade",
+            vec![source.clone().into(), 0.0.into(), 1.0.into()],
+        );
+        assert_eq!(8, notes(all).len());
+
+        let none = call(
+            "degrade",
+            vec![source.clone().into(), 1.0.into(), 1.0.into()],
+        );
+        assert!(notes(none).is_empty());
+
+        let some =
+            notes(call("degrade", vec![source.into(), 0.5.into(), 1.0.into()]));
+        assert!(!some.is_empty() && some.len() < 8);
+    }
+
+    #[test]
+    fn palindrome_and_repeat() {
+        let pattern = call("palindrome", vec![pattern(&[0, 1]).into()]);
+        assert_eq!(vec![(0, 60), (2, 61), (4, 61), (6, 60)], notes(pattern));
+
+        let pattern =
+            call("repeat", vec![self::pattern(&[0, 1]).into(), 2.0.into()]);
+        assert_eq!(vec![(0, 60), (2, 61), (4, 60), (6, 61)], notes(pattern));
+
+        // the rest after the events is repeated too
+        let mut source = self::pattern(&[0, 1]);
+        source.set_length(Some((3, 0, RESOLUTION).into()));
+        let pattern = call("repeat", vec![source.into(), 2.0.into()]);
+        assert_eq!(6.0, pattern.length().as_f64());
+        assert_eq!(vec![(0, 60), (2, 61), (6, 60), (8, 61)], notes(pattern));
+
+        for times in &[0.0, 1.5, 1e12] {
+            let arguments = vec![self::pattern(&[0]).into(), (*times).into()];
+            assert!(interpreter::apply(Box::new(Repeat::default()), arguments)
+                .is_err());
+        }
+    }
+}
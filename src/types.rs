@@ -1,16 +1,20 @@
 mod function;
+mod groove;
 mod mixer;
-mod pattern;
+pub mod pattern;
+mod tuning;
 
 pub use function::*;
+pub use groove::*;
 pub use mixer::*;
 pub use pattern::*;
 use std::collections::HashMap;
 use std::fmt;
-use std::rc::Rc;
+pub use tuning::*;
 
 type PremitiveResult<T> = Result<T, PrimitiveError>;
 
+#[allow(clippy::large_enum_variant)]
 #[derive(Debug, Clone)]
 pub enum Value {
     Identifier(Identifier),
@@ -21,13 +25,94 @@ pub enum Value {
     Array(Vec<Value>),
     Function(Box<dyn Function<Item = Value>>),
     Pattern(Pattern),
-    Mixer, // we'll get mixer from context
-    Track(Rc<Track>),
-    Slot(Rc<Slot>),
+    // the mixer, the tracks and the slots are looked up in the context's
+    // mixer, so the values are only references to them
+    Mixer,
+    Track(usize),
+    Slot(usize, usize),
     Void(()),
     Nothing,
 }
 
+impl Value {
+    pub fn type_id(&self) -> TypeId {
+        match self {
+            Value::Identifier(_) => TypeId::Identifier,
+            Value::Boolean(_) => TypeId::Boolean,
+            Value::Number(_) => TypeId::Number,
+            Value::String(_) => TypeId::String,
+            Value::Properties(_) => TypeId::Properties,
+            Value::Array(_) => TypeId::Array,
+            Value::Function(_) => TypeId::Function,
+            Value::Pattern(_) => TypeId::Pattern,
+            Value::Mixer => TypeId::Mixer,
+            Value::Track(_) => TypeId::Track,
+            Value::Slot(..) => TypeId::Slot,
+            Value::Void(_) => TypeId::Void,
+            Value::Nothing => TypeId::Nothing,
+        }
+    }
+}
+
+/// Literal values are shown as they're written in the source code,
+/// the others are shown by their [TypeId](enum.TypeId.html).
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Identifier(value) => write!(f, "{}", value.0),
+            Value::Boolean(value) => write!(f, "{}", value),
+            Value::Number(value) => write!(f, "{}", value),
+            Value::String(value) => write!(f, "{:?}", value),
+            Value::Properties(properties) => {
+                let mut properties: Vec<(&Identifier, &Value)> =
+                    properties.0.iter().collect();
+                properties.sort_by_key(|(key, _)| key.0.as_str());
+                let properties: Vec<String> = properties
+                    .into_iter()
+                    .map(|(key, value)| format!("{}: {}", key.0, value))
+                    .collect();
+                write!(f, "{{{}}}", properties.join(", "))
+            }
+            Value::Array(values) => {
+                let values: Vec<String> =
+                    values.iter().map(ToString::to_string).collect();
+                write!(f, "[{}]", values.join(", "))
+            }
+            value => write!(f, "{}", value.type_id()),
+        }
+    }
+}
+
+impl HasProperties for Value {
+    fn property(&self, key: &Identifier) -> Option<Value> {
+        match self {
+            Value::Properties(properties) => properties.0.get(key).cloned(),
+            Value::Pattern(pattern) => pattern.property(key),
+            _ => None,
+        }
+    }
+
+    fn set_property(
+        &mut self,
+        key: &Identifier,
+        value: Value,
+    ) -> PremitiveResult<()> {
+        match self {
+            Value::Properties(properties) => {
+                properties.0.insert(key.clone(), value);
+                Ok(())
+            }
+            Value::Pattern(pattern) => pattern.set_property(key, value),
+            _ => Err(PrimitiveError::SetProperty {
+                property_name: key.0.clone(),
+                assignee_name: self.type_id().to_string(),
+                cause: "the value has no properties".to_string(),
+            }),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum TypeId {
     Identifier,
     Boolean,
@@ -82,8 +167,6 @@ impl_from_for_value_wrapper!(String, String);
 impl_from_for_value_wrapper!(Properties, Properties);
 impl_from_for_value_wrapper!(Vec<Value>, Array);
 impl_from_for_value_wrapper!(Pattern, Pattern);
-impl_from_for_value_wrapper!(Rc<Track>, Track);
-impl_from_for_value_wrapper!(Rc<Slot>, Slot);
 impl_from_for_value_wrapper!(Box<dyn Function<Item = Value>>, Function);
 
 #[derive(Debug, Clone, Hash, Eq, PartialEq)]
@@ -101,7 +184,7 @@ pub trait HasProperties {
     ) -> PremitiveResult<()>;
 }
 
-#[derive(Debug, Fail)]
+#[derive(Debug, Fail, PartialEq)]
 pub enum PrimitiveError {
     #[fail(
         display = "Cannot set property {} for {}: {}",
@@ -31,3 +31,60 @@ impl Clone for Box<dyn Function<Item = Value>> {
         self.clone_box()
     }
 }
+
+/// A function with some of its last arguments already set. It's used to
+/// pass functions as arguments, i.e. `(transpose 3)` is the `transpose`
+/// function, which waits for a pattern.
+#[derive(Debug, Clone)]
+pub struct Partial {
+    function: Box<dyn Function<Item = Value>>,
+    bound: Vec<Value>,
+}
+
+impl Partial {
+    pub fn new(
+        function: Box<dyn Function<Item = Value>>,
+        bound: Vec<Value>,
+    ) -> Self {
+        Partial { function, bound }
+    }
+}
+
+impl Function for Partial {
+    fn identifier(&self) -> Identifier {
+        self.function.identifier()
+    }
+
+    fn arguments(&self) -> Vec<TypeId> {
+        let mut arguments = self.function.arguments();
+        arguments.truncate(arguments.len().saturating_sub(self.bound.len()));
+        arguments
+    }
+
+    fn returns(&self) -> TypeId {
+        self.function.returns()
+    }
+
+    fn set_arguments(&mut self, mut arguments: Vec<Value>) {
+        arguments.extend(self.bound.iter().cloned());
+        self.function.set_arguments(arguments);
+    }
+}
+
+impl Guide for Partial {
+    fn description(&self) -> &'static str {
+        self.function.description()
+    }
+
+    fn help(&self) -> &'static str {
+        self.function.help()
+    }
+}
+
+impl Iterator for Partial {
+    type Item = Value;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.function.next()
+    }
+}
@@ -0,0 +1,172 @@
+use super::{Identifier, Properties, Value};
+use crate::clock::{CursorPosition, Resolution};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+
+/// Groove moves the notes starting at the steps of a beat and scales
+/// their velocities, so the patterns don't sound mechanical. A beat is
+/// divided into `subdivision` steps, i.e. `4` for sixteenths, and the
+/// offsets and the velocities are taken in turn for the steps, so they
+/// can span several beats.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(try_from = "GrooveDefinition")]
+pub struct Groove {
+    pub name: String,
+    subdivision: u64,
+    /// The shifts of the steps in ticks.
+    offsets: Vec<i64>,
+    /// The velocity scales of the steps.
+    velocities: Vec<f64>,
+    /// The name the groove is looked up by.
+    pub key: Option<String>,
+}
+
+// The groove as it's defined in the settings.
+#[derive(Deserialize)]
+struct GrooveDefinition {
+    name: String,
+    subdivision: u64,
+    offsets: Vec<i64>,
+    #[serde(default)]
+    velocities: Vec<f64>,
+}
+
+impl TryFrom<GrooveDefinition> for Groove {
+    type Error = String;
+
+    fn try_from(definition: GrooveDefinition) -> Result<Self, Self::Error> {
+        Groove::new(
+            &definition.name,
+            definition.subdivision,
+            &definition.offsets,
+            &definition.velocities,
+        )
+        .map_err(|cause| format!("groove {}: {}", definition.name, cause))
+    }
+}
+
+impl Groove {
+    pub fn new(
+        name: &str,
+        subdivision: u64,
+        offsets: &[i64],
+        velocities: &[f64],
+    ) -> Result<Self, String> {
+        if subdivision == 0 {
+            return Err("the subdivision should be positive".to_string());
+        }
+        if velocities.iter().any(|velocity| *velocity < 0.0) {
+            return Err("the velocities should be non-negative".to_string());
+        }
+
+        Ok(Groove {
+            name: name.to_string(),
+            subdivision,
+            offsets: offsets.to_vec(),
+            velocities: velocities.to_vec(),
+            key: None,
+        })
+    }
+
+    /// Swing delays each second `note`, i.e. `8` for eighths, so the pair
+    /// is split at `amount` percents instead of the middle. `50` is
+    /// straight and `66.7` is the triplet feel.
+    pub fn swing(
+        amount: f64,
+        note: u64,
+        resolution: Resolution,
+    ) -> Result<Self, String> {
+        if !(50.0..100.0).contains(&amount) {
+            return Err(format!("swing {} is out of 50..100", amount));
+        }
+        if note < 4 || !note.is_multiple_of(4) {
+            return Err(format!("cannot swing 1/{} notes", note));
+        }
+
+        let subdivision = note / 4;
+        let step = resolution as f64 / subdivision as f64;
+        let delay = ((amount - 50.0) / 50.0 * step).round() as i64;
+        Groove::new(
+            &format!("swing {} 1/{}", amount, note),
+            subdivision,
+            &[0, delay],
+            &[],
+        )
+    }
+
+    /// The offset in ticks and the velocity scale of the note starting
+    /// at `position`. The notes between the steps aren't changed.
+    pub fn at(&self, position: CursorPosition) -> (i64, f64) {
+        let ticks = position.as_ticks() * self.subdivision;
+        let resolution = position.resolution();
+        if !ticks.is_multiple_of(resolution) {
+            return (0, 1.0);
+        }
+
+        let step = (ticks / resolution) as usize;
+        let cycle = |length: usize| step % length.max(1);
+        (
+            self.offsets
+                .get(cycle(self.offsets.len()))
+                .cloned()
+                .unwrap_or(0),
+            self.velocities
+                .get(cycle(self.velocities.len()))
+                .cloned()
+                .unwrap_or(1.0),
+        )
+    }
+}
+
+/// A groove from a catalogue is its key, the others are written out as
+/// `{name: "...", subdivision: 4, offsets: [...], velocities: [...]}`,
+/// so they can be assigned back.
+impl From<&Groove> for Value {
+    fn from(groove: &Groove) -> Self {
+        if let Some(key) = groove.key.as_ref() {
+            return Value::String(key.clone());
+        }
+
+        let numbers = |numbers: Vec<f64>| {
+            Value::Array(numbers.into_iter().map(Value::Number).collect())
+        };
+        let mut properties = HashMap::new();
+        let mut insert = |key: &str, value: Value| {
+            properties.insert(Identifier(key.to_string()), value);
+        };
+        insert("name", Value::String(groove.name.clone()));
+        insert("subdivision", Value::Number(groove.subdivision as f64));
+        insert(
+            "offsets",
+            numbers(groove.offsets.iter().map(|o| *o as f64).collect()),
+        );
+        insert("velocities", numbers(groove.velocities.clone()));
+        Value::Properties(Properties(properties))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn groove_steps() {
+        let position = |ticks| CursorPosition::from_ticks(ticks, 8);
+        let groove =
+            Groove::new("shuffle", 4, &[0, 1, -1], &[1.0, 0.5]).unwrap();
+
+        assert_eq!((0, 1.0), groove.at(position(0)));
+        assert_eq!((1, 0.5), groove.at(position(2)));
+        assert_eq!((-1, 1.0), groove.at(position(4)));
+        assert_eq!((0, 0.5), groove.at(position(6)));
+        assert_eq!((0, 1.0), groove.at(position(3)));
+
+        let swing = Groove::swing(75.0, 8, 8).unwrap();
+        assert_eq!((0, 1.0), swing.at(position(8)));
+        assert_eq!((2, 1.0), swing.at(position(12)));
+        assert!(Groove::swing(40.0, 8, 8).is_err());
+        assert!(Groove::swing(60.0, 6, 8).is_err());
+        assert!(Groove::new("empty", 0, &[], &[]).is_err());
+    }
+}
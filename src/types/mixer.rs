@@ -1,28 +1,517 @@
-use super::pattern::Pattern;
-use crate::clock::Clock;
+use super::pattern::{self, Curve, Pattern, Scale};
+use super::{Groove, Tuning};
+use super::{
+    HasProperties, Identifier, PremitiveResult, PrimitiveError, Properties,
+    Value,
+};
+use crate::clock::{Bpm, Clock, CursorPosition, Quantisation};
+use crate::output::{resolve, TrackOptions};
 use std::collections::HashMap;
-use std::rc::Rc;
+use std::mem;
 
+/// Mixer owns the tracks and the tracks own the slots. The language
+/// refers to them by their indices (see
+/// [Value::Track](enum.Value.html#variant.Track)), so they're looked up
+/// each time they're accessed.
 #[derive(Debug, Clone, Default)]
 pub struct Mixer {
-    tracks: HashMap<usize, Rc<Track>>,
+    tracks: HashMap<usize, Track>,
+    // the last launched scene
+    scene: Option<usize>,
+    warnings: Vec<String>,
     pub clock: Clock,
 }
 
 impl Mixer {
-    pub fn track(&mut self, index: usize) -> Rc<Track> {
-        self.tracks.entry(index).or_default().clone()
+    /// Take the warnings about the changes, which have been adjusted,
+    /// i.e. the tempo clamped to the limits.
+    pub fn take_warnings(&mut self) -> Vec<String> {
+        mem::take(&mut self.warnings)
+    }
+
+    pub fn track(&self, index: usize) -> Option<&Track> {
+        self.tracks.get(&index)
+    }
+
+    /// Get a mutable reference to the track at `index` creating it
+    /// if it doesn't exist yet.
+    pub fn track_mut(&mut self, index: usize) -> &mut Track {
+        self.tracks.entry(index).or_default()
+    }
+
+    /// Launch the slot `index` on all the tracks. The tracks, which
+    /// don't have the slot, are stopped.
+    pub fn launch_scene(&mut self, index: usize) {
+        for track in self.tracks.values_mut() {
+            if track.slot(index).is_some() {
+                track.launch(index);
+            } else {
+                track.stop();
+            }
+        }
+        self.scene = Some(index);
+    }
+
+    /// Stop all the tracks.
+    pub fn stop(&mut self) {
+        self.tracks.values_mut().for_each(Track::stop);
+        self.scene = None;
+    }
+
+    /// The last launched scene.
+    pub fn scene(&self) -> Option<usize> {
+        self.scene
+    }
+
+    /// Get all existing tracks sorted by their indices.
+    pub fn tracks(&self) -> Vec<(usize, &Track)> {
+        let mut tracks: Vec<(usize, &Track)> = self
+            .tracks
+            .iter()
+            .map(|(index, track)| (*index, track))
+            .collect();
+        tracks.sort_by_key(|(index, _)| *index);
+        tracks
+    }
+}
+
+impl HasProperties for Mixer {
+    fn property(&self, key: &Identifier) -> Option<Value> {
+        match key.0.as_str() {
+            "tempo" => Some(Value::Number(self.clock.tempo().get())),
+            "resolution" => Some(Value::Number(self.clock.resolution() as f64)),
+            "beats_per_bar" => {
+                Some(Value::Number(self.clock.beats_per_bar() as f64))
+            }
+            "quantisation" => {
+                Some(Value::String(self.clock.quantisation().to_string()))
+            }
+            "scene" => Some(slot_value(self.scene)),
+            _ => None,
+        }
+    }
+
+    fn set_property(
+        &mut self,
+        key: &Identifier,
+        value: Value,
+    ) -> PremitiveResult<()> {
+        let error = |cause: String| PrimitiveError::SetProperty {
+            property_name: key.0.clone(),
+            assignee_name: "<Mixer>".to_string(),
+            cause,
+        };
+
+        match (key.0.as_str(), value) {
+            ("tempo", Value::Number(value)) => {
+                let tempo =
+                    Bpm::new(value).map_err(|e| error(e.to_string()))?;
+                let warning = self.clock.set_tempo(tempo);
+                self.warnings.extend(warning.map(|w| w.to_string()));
+                Ok(())
+            }
+            ("tempo", Value::Properties(ramp)) => {
+                let (tempo, beats, curve) =
+                    tempo_ramp(&ramp, self.clock.beats_per_bar())
+                        .map_err(error)?;
+                let length =
+                    CursorPosition::from_f64(beats, self.clock.resolution());
+                let warning = self.clock.ramp_tempo(tempo, length, curve);
+                self.warnings.extend(warning.map(|w| w.to_string()));
+                Ok(())
+            }
+            ("tempo", value) => Err(error(format!(
+                "expected <Number>, found {}",
+                value.type_id()
+            ))),
+            ("resolution", _) => {
+                Err(error("the resolution is set in the settings".into()))
+            }
+            ("beats_per_bar", Value::Number(value))
+                if value >= 1.0 && value.fract() == 0.0 =>
+            {
+                self.clock.set_beats_per_bar(value as u64);
+                Ok(())
+            }
+            ("beats_per_bar", value) => Err(error(format!(
+                "expected a positive integer, found {}",
+                value
+            ))),
+            ("quantisation", Value::String(value)) => {
+                let quantisation: Quantisation =
+                    value.parse().map_err(error)?;
+                self.clock.set_quantisation(quantisation);
+                Ok(())
+            }
+            ("quantisation", value) => Err(error(format!(
+                "expected <String>, found {}",
+                value.type_id()
+            ))),
+            ("scene", value) => {
+                match slot_index(value).map_err(error)? {
+                    Some(index) => self.launch_scene(index),
+                    None => self.stop(),
+                }
+                Ok(())
+            }
+            _ => Err(error("unknown property".into())),
+        }
     }
 }
 
 #[derive(Debug, Clone, Default)]
 pub struct Track {
-    slots: HashMap<usize, Rc<Slot>>,
+    slots: HashMap<usize, Slot>,
+    output: Option<String>,
+    options: TrackOptions,
+    scale: Option<Scale>,
+    tuning: Option<Tuning>,
+    groove: Option<Groove>,
+    velocity: Option<f64>,
+    playing: Option<usize>,
+    is_stopped: bool,
 }
 
 impl Track {
-    pub fn slot(&mut self, index: usize) -> Rc<Slot> {
-        self.slots.entry(index).or_default().clone()
+    pub fn slot(&self, index: usize) -> Option<&Slot> {
+        self.slots.get(&index)
+    }
+
+    /// Get a mutable reference to the slot at `index` creating it
+    /// if it doesn't exist yet.
+    pub fn slot_mut(&mut self, index: usize) -> &mut Slot {
+        self.slots.entry(index).or_default()
+    }
+
+    /// The slot, which is playing. Only one slot of a track plays
+    /// at a time.
+    pub fn playing(&self) -> Option<usize> {
+        self.playing
+    }
+
+    /// Play the slot `index` instead of the current one. The player
+    /// switches the slots at the next beat.
+    pub fn launch(&mut self, index: usize) {
+        self.playing = Some(index);
+        self.is_stopped = false;
+    }
+
+    pub fn stop(&mut self) {
+        self.playing = None;
+        self.is_stopped = true;
+    }
+
+    /// Launch the slot `index` if the track has never played or been
+    /// stopped yet, so assigning a pattern to a new track plays it.
+    pub fn launch_if_idle(&mut self, index: usize) {
+        if self.playing.is_none() && !self.is_stopped {
+            self.launch(index);
+        }
+    }
+
+    /// Get all existing slots sorted by their indices.
+    pub fn slots(&self) -> Vec<(usize, &Slot)> {
+        let mut slots: Vec<(usize, &Slot)> = self
+            .slots
+            .iter()
+            .map(|(index, slot)| (*index, slot))
+            .collect();
+        slots.sort_by_key(|(index, _)| *index);
+        slots
+    }
+
+    /// The name of the output the track is routed to. `None` means
+    /// the default output.
+    pub fn output(&self) -> Option<&str> {
+        self.output.as_deref()
+    }
+
+    pub fn set_output(&mut self, output: Option<String>) {
+        self.output = output;
+    }
+
+    /// The scale of the track's patterns, which don't set their own one.
+    pub fn scale(&self) -> Option<&Scale> {
+        self.scale.as_ref()
+    }
+
+    /// Set the scale of the track and of its current patterns. `None`
+    /// sets the default scale.
+    pub fn set_scale(&mut self, scale: Option<Scale>) {
+        let default = scale.clone().unwrap_or_default();
+        for slot in self.slots.values_mut() {
+            slot.pattern.set_default_scale(default.clone());
+        }
+        self.scale = scale;
+    }
+
+    /// The tuning of the track's patterns, which don't set their own one.
+    pub fn tuning(&self) -> Option<&Tuning> {
+        self.tuning.as_ref()
+    }
+
+    /// Set the tuning of the track and of its current patterns. `None`
+    /// sets the default tuning.
+    pub fn set_tuning(&mut self, tuning: Option<Tuning>) {
+        let default = tuning.clone().unwrap_or_default();
+        for slot in self.slots.values_mut() {
+            slot.pattern.set_default_tuning(default.clone());
+        }
+        self.tuning = tuning;
+    }
+
+    /// The groove of the track's patterns, which don't set their own one.
+    pub fn groove(&self) -> Option<&Groove> {
+        self.groove.as_ref()
+    }
+
+    /// Set the groove of the track and of its current patterns.
+    pub fn set_groove(&mut self, groove: Option<Groove>) {
+        for slot in self.slots.values_mut() {
+            slot.pattern.set_default_groove(groove.clone());
+        }
+        self.groove = groove;
+    }
+
+    /// The velocity of the track's patterns, which don't set their own
+    /// one.
+    pub fn velocity(&self) -> Option<f64> {
+        self.velocity
+    }
+
+    /// Set the velocity of the track and of its current patterns.
+    pub fn set_velocity(&mut self, velocity: Option<f64>) {
+        for slot in self.slots.values_mut() {
+            slot.pattern.set_default_velocity(velocity);
+        }
+        self.velocity = velocity;
+    }
+
+    /// Output related properties of the track.
+    pub fn options(&self) -> &TrackOptions {
+        &self.options
+    }
+}
+
+impl HasProperties for Track {
+    fn property(&self, key: &Identifier) -> Option<Value> {
+        let string = |value: &Option<String>| {
+            value.clone().map(Value::String).unwrap_or(Value::Nothing)
+        };
+
+        match key.0.as_str() {
+            "output" => Some(string(&self.output)),
+            "host" => Some(string(&self.options.host)),
+            "port" => Some(
+                self.options
+                    .port
+                    .map(|port| Value::Number(f64::from(port)))
+                    .unwrap_or(Value::Nothing),
+            ),
+            "prefix" => Some(string(&self.options.prefix)),
+            "scale" => Some(string(
+                &self.scale.as_ref().map(|scale| scale.key().to_string()),
+            )),
+            "tuning" => Some(string(
+                &self.tuning.as_ref().map(|tuning| tuning.name.clone()),
+            )),
+            "groove" => Some(
+                self.groove
+                    .as_ref()
+                    .map(Value::from)
+                    .unwrap_or(Value::Nothing),
+            ),
+            "channel" => Some(Value::Number(f64::from(self.options.channel()))),
+            "mute" => Some(Value::Boolean(self.options.mute)),
+            "solo" => Some(Value::Boolean(self.options.solo)),
+            "velocity" => {
+                Some(self.velocity.map(Value::Number).unwrap_or(Value::Nothing))
+            }
+            "volume" => Some(Value::Number(self.options.volume.unwrap_or(1.0))),
+            "playing" => Some(slot_value(self.playing)),
+            _ => None,
+        }
+    }
+
+    fn set_property(
+        &mut self,
+        key: &Identifier,
+        value: Value,
+    ) -> PremitiveResult<()> {
+        let error = |cause: &str| PrimitiveError::SetProperty {
+            property_name: key.0.clone(),
+            assignee_name: "<Track>".to_string(),
+            cause: cause.to_string(),
+        };
+
+        let string = |value: Value| match value {
+            Value::String(value) => Ok(Some(value)),
+            Value::Nothing => Ok(None),
+            value => Err(error(&format!(
+                "expected <String> or <Nothing>, found {}",
+                value.type_id()
+            ))),
+        };
+
+        match key.0.as_str() {
+            "output" => self.output = string(value)?,
+            "host" => {
+                let host = string(value)?;
+                // the host is resolved once here, not by the outputs
+                self.options.address = host
+                    .as_ref()
+                    .map(|host| resolve(host, 0).map(|address| address.ip()))
+                    .transpose()
+                    .map_err(|e| error(&e.to_string()))?;
+                self.options.host = host;
+            }
+            "port" => {
+                self.options.port = match value {
+                    Value::Number(port)
+                        if port.fract() == 0.0
+                            && port >= 0.0
+                            && port <= f64::from(u16::MAX) =>
+                    {
+                        Some(port as u16)
+                    }
+                    Value::Number(port) => {
+                        return Err(error(&format!(
+                            "{} is not a valid port",
+                            port
+                        )))
+                    }
+                    Value::Nothing => None,
+                    value => {
+                        return Err(error(&format!(
+                            "expected <Number> or <Nothing>, found {}",
+                            value.type_id()
+                        )))
+                    }
+                }
+            }
+            "prefix" => self.options.prefix = string(value)?,
+            "channel" => {
+                self.options.channel = match value {
+                    Value::Number(channel)
+                        if channel.fract() == 0.0
+                            && (0.0..16.0).contains(&channel) =>
+                    {
+                        Some(channel as u8)
+                    }
+                    Value::Number(channel) => {
+                        return Err(error(&format!(
+                            "{} is not a valid channel",
+                            channel
+                        )))
+                    }
+                    Value::Nothing => None,
+                    value => {
+                        return Err(error(&format!(
+                            "expected <Number> or <Nothing>, found {}",
+                            value.type_id()
+                        )))
+                    }
+                }
+            }
+            "mute" | "solo" => {
+                let flag = match value {
+                    Value::Boolean(flag) => flag,
+                    value => {
+                        return Err(error(&format!(
+                            "expected <Boolean>, found {}",
+                            value.type_id()
+                        )))
+                    }
+                };
+                if key.0 == "mute" {
+                    self.options.mute = flag;
+                } else {
+                    self.options.solo = flag;
+                }
+            }
+            "volume" => {
+                self.options.volume = match value {
+                    Value::Number(volume) if (0.0..=1.0).contains(&volume) => {
+                        Some(volume)
+                    }
+                    Value::Number(volume) => {
+                        return Err(error(&format!(
+                            "{} is out of range 0..1",
+                            volume
+                        )))
+                    }
+                    Value::Nothing => None,
+                    value => {
+                        return Err(error(&format!(
+                            "expected <Number> or <Nothing>, found {}",
+                            value.type_id()
+                        )))
+                    }
+                }
+            }
+            "velocity" => self
+                .set_velocity(pattern::velocity(value).map_err(|e| error(&e))?),
+            "playing" => match slot_index(value).map_err(|e| error(&e))? {
+                Some(index) => self.launch(index),
+                None => self.stop(),
+            },
+            _ => return Err(error("unknown property")),
+        }
+
+        Ok(())
+    }
+}
+
+fn slot_value(index: Option<usize>) -> Value {
+    index
+        .map(|index| Value::Number(index as f64))
+        .unwrap_or(Value::Nothing)
+}
+
+// The target tempo, the length in beats and the curve of a ramp like
+// `{bpm: 140, bars: 8, curve: "exponential"}`. The length is the sum of
+// `bars` and `beats`.
+fn tempo_ramp(
+    ramp: &Properties,
+    beats_per_bar: u64,
+) -> Result<(Bpm, f64, Curve), String> {
+    let number = |name: &str| match ramp.0.get(&Identifier(name.into())) {
+        Some(Value::Number(value)) if *value >= 0.0 => Ok(Some(*value)),
+        Some(value) => Err(format!(
+            "expected {} as a non-negative <Number>, found {}",
+            name, value
+        )),
+        None => Ok(None),
+    };
+
+    let tempo = match number("bpm")? {
+        Some(bpm) => Bpm::new(bpm).map_err(|e| e.to_string())?,
+        None => return Err("expected bpm of the ramp".to_string()),
+    };
+    let bars = number("bars")?.unwrap_or_default();
+    let beats = bars * beats_per_bar as f64 + number("beats")?.unwrap_or(0.0);
+    let curve = match ramp.0.get(&Identifier("curve".into())) {
+        Some(Value::String(name)) => name.parse()?,
+        Some(value) => {
+            return Err(format!("expected curve as <String>, found {}", value))
+        }
+        None => Curve::default(),
+    };
+
+    Ok((tempo, beats, curve))
+}
+
+// A slot index or `false` to stop.
+fn slot_index(value: Value) -> Result<Option<usize>, String> {
+    match value {
+        Value::Number(index) if index.fract() == 0.0 && index >= 0.0 => {
+            Ok(Some(index as usize))
+        }
+        Value::Boolean(false) | Value::Nothing => Ok(None),
+        value => Err(format!(
+            "expected slot index as <Number> or false, found {}",
+            value
+        )),
     }
 }
 
@@ -30,3 +519,73 @@ impl Track {
 pub struct Slot {
     pattern: Pattern,
 }
+
+impl Slot {
+    pub fn pattern(&self) -> &Pattern {
+        &self.pattern
+    }
+
+    pub fn pattern_mut(&mut self) -> &mut Pattern {
+        &mut self.pattern
+    }
+
+    pub fn set_pattern(&mut self, pattern: Pattern) {
+        self.pattern = pattern;
+    }
+
+    /// Take the pattern leaving an empty one in the slot.
+    pub fn take_pattern(&mut self) -> Pattern {
+        mem::take(&mut self.pattern)
+    }
+
+    /// Put the `pattern` into the slot and return the previous one.
+    pub fn swap_pattern(&mut self, pattern: Pattern) -> Pattern {
+        mem::replace(&mut self.pattern, pattern)
+    }
+}
+
+/// The properties of a slot are the ones of its pattern.
+impl HasProperties for Slot {
+    fn property(&self, key: &Identifier) -> Option<Value> {
+        self.pattern.property(key)
+    }
+
+    fn set_property(
+        &mut self,
+        key: &Identifier,
+        value: Value,
+    ) -> PremitiveResult<()> {
+        self.pattern.set_property(key, value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::Cursor;
+
+    fn pattern(degree: u64) -> Pattern {
+        let mut pattern = Pattern::new(Cursor::new(1));
+        pattern.schedule_degree(
+            degree.into(),
+            (0, 0, 1).into(),
+            (1, 0, 1).into(),
+        );
+        pattern
+    }
+
+    #[test]
+    fn take_and_swap_patterns() {
+        let mut mixer = Mixer::default();
+        assert!(mixer.track(0).is_none());
+
+        mixer.track_mut(0).slot_mut(1).set_pattern(pattern(1));
+        let previous = mixer.track_mut(0).slot_mut(1).swap_pattern(pattern(2));
+        assert_eq!(pattern(1), previous);
+
+        let slot = mixer.track_mut(0).slot_mut(1);
+        assert_eq!(pattern(2), slot.take_pattern());
+        assert_eq!(&Pattern::default(), slot.pattern());
+        assert_eq!(1, mixer.tracks().len());
+    }
+}
@@ -1,11 +1,26 @@
+use std::collections::HashMap;
+use std::convert::TryFrom;
 use std::fmt::Debug;
+use std::str::FromStr;
 
-use crate::clock::{Cursor, CursorPosition, Duration};
+use super::{
+    Groove, HasProperties, Identifier, PremitiveResult, PrimitiveError, Tuning,
+};
+use crate::clock::{Cursor, CursorPosition, Duration, Resolution};
 
 use serde::Deserialize;
 
 const DEFAULT_SCALE_NAME: &str = "Chromatic";
+const DEFAULT_SCALE_KEY: &str = "chromatic";
+const SCALES: &str = include_str!("../../scales.toml");
 const DEFAULT_PITCH_SET: [u64; 12] = [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11];
+// the highest degree, which can be written in a pattern (`f`)
+const MAX_DEGREE: f64 = 15.0;
+/// The velocity of the notes of the patterns and the tracks, which don't
+/// set their own one.
+pub const DEFAULT_VELOCITY: f64 = 0.8;
+// the velocity change of an accent or a ghost note
+const ACCENT: f64 = 0.2;
 
 /// Pattern combines several [EventStream](struct.EventStream.html)s
 /// and produces [Value](enum.Value.html)s for current [Cursor](../clock/struct.Cursor.html)
@@ -17,6 +32,26 @@ pub struct Pattern {
     root: EventStream<Root>,
     octave: EventStream<Octave>,
     modulation: EventStream<Modulation>,
+    // glides don't loop by themselves, they're restarted with degrees
+    glide: EventStream<Glide>,
+    // the sounding glides with the number of ticks passed and their
+    // lengths in ticks
+    gliding: Vec<(Glide, u64, u64)>,
+    // used when there are no scheduled scales
+    default_scale: Scale,
+    tuning: Option<Tuning>,
+    default_tuning: Tuning,
+    groove: Option<Groove>,
+    // used when the pattern has no own groove
+    default_groove: Option<Groove>,
+    velocity: Option<f64>,
+    // used when the pattern has no own velocity
+    default_velocity: Option<f64>,
+    // the explicit length and loop points, without them the pattern
+    // loops when its events run out
+    length: Option<Duration>,
+    loop_start: Option<CursorPosition>,
+    loop_end: Option<CursorPosition>,
     cursor: Cursor,
     start_position: CursorPosition,
     is_loop: bool,
@@ -25,6 +60,10 @@ pub struct Pattern {
 
 macro_rules! impl_schedule_method {
     ($name:ident, $field:ident, $e_type:ty) => {
+        impl_schedule_method!($name, $field, $e_type, false);
+    };
+    // the grooved values start off the grid, like in `apply_groove`
+    ($name:ident, $field:ident, $e_type:ty, $grooved:expr) => {
         /// Events is scheduled at relative to the pattern position
         /// i.e. the first event's position is (0, 0) and it's
         /// independent of cursor's position.
@@ -34,17 +73,20 @@ macro_rules! impl_schedule_method {
             position: CursorPosition,
             duration: Duration,
         ) {
-            self.$field.add_event(Event {
-                value: value.clone(),
-                position,
-                state: EventState::On,
-            });
+            let mut start = Event::new(value.clone(), position, EventState::On)
+                .with_duration(duration);
+            match self.groove() {
+                Some(groove) if $grooved => start.apply_groove(Some(groove)),
+                _ => (),
+            }
+            self.$field.add_event(start);
 
-            self.$field.add_event(Event {
+            self.$field.add_event(Event::new(
                 value,
-                position: (position + duration) - 1,
-                state: EventState::Off,
-            });
+                (position + duration) - 1,
+                EventState::Off,
+            ));
+            self.update_loop_points();
         }
     };
 }
@@ -60,6 +102,18 @@ impl Pattern {
             root: EventStream::new(vec![], cursor.resolution()),
             octave: EventStream::new(vec![], cursor.resolution()),
             modulation: EventStream::new(vec![], cursor.resolution()),
+            glide: EventStream::new(vec![], cursor.resolution()),
+            gliding: Vec::new(),
+            default_scale: Scale::default(),
+            tuning: None,
+            default_tuning: Tuning::default(),
+            groove: None,
+            default_groove: None,
+            velocity: None,
+            default_velocity: None,
+            length: None,
+            loop_start: None,
+            loop_end: None,
             start_position,
             cursor,
             is_loop: false,
@@ -92,12 +146,19 @@ impl Pattern {
 
     /// Reset patern to start position.
     pub fn reset(&mut self) {
+        self.is_finished = false;
         self.cursor.reset();
         self.degree.reset();
         self.scale.reset();
         self.root.reset();
         self.octave.reset();
         self.modulation.reset();
+        self.glide.reset();
+        self.gliding.clear();
+    }
+
+    pub fn resolution(&self) -> Resolution {
+        self.cursor.resolution()
     }
 
     pub fn start_position(&self) -> CursorPosition {
@@ -110,6 +171,361 @@ impl Pattern {
         self.root.sort();
         self.octave.sort();
         self.modulation.sort();
+        self.glide.sort();
+    }
+
+    /// Merge events of `other` into `self`, so both patterns sound
+    /// simultaneously. The events of `other` are considered relative
+    /// to `self`'s start position.
+    pub fn merge(&mut self, other: Pattern) {
+        self.degree.append(other.degree);
+        self.scale.append(other.scale);
+        self.root.append(other.root);
+        self.octave.append(other.octave);
+        self.modulation.append(other.modulation);
+        self.glide.append(other.glide);
+        if self.groove().is_some() {
+            self.apply_groove();
+        }
+        self.update_loop_points();
+    }
+
+    /// Use the `scale` for the whole pattern. `None` removes the scheduled
+    /// scales, so the default one is used.
+    pub fn set_scale(&mut self, scale: Option<Scale>) {
+        let length = self.length();
+        self.scale.clear();
+        if let Some(scale) = scale {
+            self.schedule_scale(
+                scale,
+                CursorPosition::new(self.resolution()),
+                length,
+            );
+        }
+    }
+
+    /// The scale at the start of the pattern.
+    pub fn scale(&self) -> &Scale {
+        self.scale.first().unwrap_or(&self.default_scale)
+    }
+
+    /// The root at the start of the pattern.
+    pub fn root(&self) -> Root {
+        self.root.first().cloned().unwrap_or_default()
+    }
+
+    /// Use the `root` for the whole pattern.
+    pub fn set_root(&mut self, root: Root) {
+        let length = self.length();
+        self.root.clear();
+        self.schedule_root(
+            root,
+            CursorPosition::new(self.resolution()),
+            length,
+        );
+    }
+
+    /// The octave at the start of the pattern.
+    pub fn octave(&self) -> Octave {
+        self.octave.first().cloned().unwrap_or_default()
+    }
+
+    /// Transpose the pattern, so it starts at the `octave`.
+    pub fn set_octave(&mut self, octave: u64) {
+        let shift = octave as i64 - self.octave().octave as i64;
+        for event in self.octave.events.iter_mut() {
+            let octave = (event.value.octave as i64 + shift).max(0) as u64;
+            event.value.set_as_octave(octave);
+        }
+        if self.octave.is_empty() {
+            let length = self.length();
+            self.schedule_octave(
+                Octave::with_octave(octave),
+                CursorPosition::new(self.resolution()),
+                length,
+            );
+        }
+    }
+
+    /// Set the scale, which is used if there are no scales scheduled.
+    pub fn set_default_scale(&mut self, scale: Scale) {
+        self.default_scale = scale;
+    }
+
+    /// Set the curve of all the scheduled glides.
+    pub fn set_curve(&mut self, curve: Curve) {
+        for event in self.glide.events.iter_mut() {
+            event.value.curve = curve;
+        }
+    }
+
+    /// Set the tuning of the pattern. `None` means the default tuning.
+    pub fn set_tuning(&mut self, tuning: Option<Tuning>) {
+        self.tuning = tuning;
+    }
+
+    /// Set the tuning, which is used if the pattern has no own tuning.
+    pub fn set_default_tuning(&mut self, tuning: Tuning) {
+        self.default_tuning = tuning;
+    }
+
+    pub fn tuning(&self) -> &Tuning {
+        self.tuning.as_ref().unwrap_or(&self.default_tuning)
+    }
+
+    /// Set the groove of the pattern. `None` means the default groove.
+    pub fn set_groove(&mut self, groove: Option<Groove>) {
+        self.groove = groove;
+        self.apply_groove();
+    }
+
+    /// Set the groove, which is used if the pattern has no own groove.
+    pub fn set_default_groove(&mut self, groove: Option<Groove>) {
+        self.default_groove = groove;
+        self.apply_groove();
+    }
+
+    pub fn groove(&self) -> Option<&Groove> {
+        self.groove.as_ref().or(self.default_groove.as_ref())
+    }
+
+    /// Set the velocity of the notes from 0 to 1. Accents and ghost notes
+    /// are relative to it. `None` means the default velocity.
+    pub fn set_velocity(&mut self, velocity: Option<f64>) {
+        self.velocity = velocity;
+    }
+
+    /// Set the velocity, which is used if the pattern has no own velocity.
+    pub fn set_default_velocity(&mut self, velocity: Option<f64>) {
+        self.default_velocity = velocity;
+    }
+
+    pub fn velocity(&self) -> f64 {
+        self.velocity
+            .or(self.default_velocity)
+            .unwrap_or(DEFAULT_VELOCITY)
+    }
+
+    // Move the starts of the notes and the modulations according to
+    // the groove. The other values change on the grid.
+    fn apply_groove(&mut self) {
+        let groove = self.groove().cloned();
+        self.degree.apply_groove(groove.as_ref());
+        self.glide.apply_groove(groove.as_ref());
+        self.modulation.apply_groove(groove.as_ref());
+        self.reset();
+    }
+
+    /// Append `other` after the end of `self`, so the patterns sound
+    /// one after another.
+    pub fn append(&mut self, mut other: Pattern) {
+        let length = self.length();
+        let total = if self.length.is_some() || other.length.is_some() {
+            Some(length + other.length())
+        } else {
+            None
+        };
+        other.map_timing(|position, duration| {
+            vec![(position + length, duration)]
+        });
+        self.merge(other);
+        self.set_length(total);
+    }
+
+    /// The explicit length of the pattern or the length of its events
+    /// rounded up to whole beats, since such patterns loop at beat
+    /// boundaries.
+    pub fn length(&self) -> Duration {
+        self.length.unwrap_or_else(|| self.events_length())
+    }
+
+    /// The length of the events rounded up to whole beats regardless of
+    /// the explicit length.
+    pub fn events_length(&self) -> Duration {
+        let end = [
+            self.degree.end(),
+            self.scale.end(),
+            self.root.end(),
+            self.octave.end(),
+            self.modulation.end(),
+            self.glide.end(),
+        ]
+        .iter()
+        .flatten()
+        .max()
+        .cloned()
+        .unwrap_or_else(|| Duration::new(self.resolution()));
+
+        if end.tick() == 0 {
+            end
+        } else {
+            (end.beat() + 1, 0, self.resolution()).into()
+        }
+    }
+
+    /// Set the length of the pattern, so it loops after it even if its
+    /// events end earlier or later. `None` means the length of the events.
+    pub fn set_length(&mut self, length: Option<Duration>) {
+        self.length = length;
+        self.update_loop_points();
+    }
+
+    /// Set the position the pattern returns to, when it reaches the loop
+    /// end. The part before it is played only once. `None` means the
+    /// start of the pattern.
+    pub fn set_loop_start(&mut self, position: Option<CursorPosition>) {
+        self.loop_start = position;
+        self.update_loop_points();
+    }
+
+    /// Set the position the pattern loops at. `None` means the end of
+    /// the pattern.
+    pub fn set_loop_end(&mut self, position: Option<CursorPosition>) {
+        self.loop_end = position;
+        self.update_loop_points();
+    }
+
+    /// Multiply the explicit length by the number of `times` the events
+    /// are repeated. The loop points aren't changed.
+    pub fn repeat_length(&mut self, times: f64) {
+        self.length = self.length.map(|length| length * times);
+        self.update_loop_points();
+    }
+
+    /// Multiply the explicit length and the loop points by the `factor`
+    /// the events are stretched by.
+    pub fn stretch_length(&mut self, factor: f64) {
+        self.length = self.length.map(|length| length * factor);
+        self.loop_start = self.loop_start.map(|start| start * factor);
+        self.loop_end = self.loop_end.map(|end| end * factor);
+        self.update_loop_points();
+    }
+
+    /// The start and the end of the loop. The start is ignored if it's
+    /// not before the end.
+    pub fn loop_points(&self) -> (CursorPosition, CursorPosition) {
+        let zero = CursorPosition::new(self.resolution());
+        let end = self.loop_end.unwrap_or_else(|| self.length());
+        let start = self.loop_start.filter(|start| *start < end);
+        (start.unwrap_or(zero), end)
+    }
+
+    // The streams loop at the pattern's loop points only if they're set
+    // explicitly, otherwise each stream loops when its events run out.
+    fn update_loop_points(&mut self) {
+        let loop_points = if self.length.is_some()
+            || self.loop_start.is_some()
+            || self.loop_end.is_some()
+        {
+            Some(self.loop_points())
+        } else {
+            None
+        };
+
+        self.degree.set_loop_points(loop_points);
+        self.scale.set_loop_points(loop_points);
+        self.root.set_loop_points(loop_points);
+        self.octave.set_loop_points(loop_points);
+        self.modulation.set_loop_points(loop_points);
+        self.glide.set_loop_points(loop_points);
+    }
+
+    /// Replace the position and the duration of each scheduled value
+    /// with the positions and durations returned by `f`. A value can be
+    /// dropped by returning an empty vector or repeated by returning
+    /// several positions. The same function is applied to all
+    /// the streams, so it should return the same for the same input.
+    pub fn map_timing<F>(&mut self, f: F)
+    where
+        F: Fn(CursorPosition, Duration) -> Vec<(CursorPosition, Duration)>,
+    {
+        self.degree.map_timing(&f);
+        self.scale.map_timing(&f);
+        self.root.map_timing(&f);
+        self.octave.map_timing(&f);
+        self.modulation.map_timing(&f);
+        self.glide.map_timing(&f);
+        self.apply_groove();
+        self.update_loop_points();
+    }
+
+    /// Get the scheduled degrees in the order of their positions.
+    pub fn degrees(&self) -> Vec<Degree> {
+        let mut starts = self.degree.starts();
+        starts.sort_by_key(|e| e.position);
+        starts.into_iter().map(|e| e.value).collect()
+    }
+
+    /// Get the scheduled degrees with their positions and durations sorted
+    /// by the position.
+    pub fn degree_events(&self) -> Vec<(Degree, CursorPosition, Duration)> {
+        let mut starts = self.degree.starts();
+        starts.sort_by_key(|e| e.position);
+        starts
+            .into_iter()
+            .map(|e| (e.value, e.position, e.duration))
+            .collect()
+    }
+
+    /// Schedule the degrees of `source` as the modulation `name`. Degrees
+    /// from `0` to `f` are mapped linearly into `range` and the glides
+    /// become a modulation per tick. Octaves are ignored.
+    pub fn add_modulation(
+        &mut self,
+        name: &str,
+        source: &Pattern,
+        range: (f64, f64),
+    ) {
+        let value = |degree: &Degree| {
+            let step = degree.value as f64 + degree.alteration as f64;
+            range.0 + (range.1 - range.0) * step / MAX_DEGREE
+        };
+
+        for (degree, position, duration) in source.degree_events() {
+            self.schedule_modulation(
+                Modulation::new(name, value(&degree)),
+                position,
+                duration,
+            );
+        }
+
+        let tick = CursorPosition::from_ticks(1, self.resolution());
+        for event in source.glide.starts() {
+            let (from, to) = (value(&event.value.from), value(&event.value.to));
+            let length = event.duration.as_ticks().max(1);
+            for n in 0..length {
+                let progress = if length > 1 {
+                    n as f64 / (length - 1) as f64
+                } else {
+                    1.0
+                };
+                self.schedule_modulation(
+                    Modulation::new(
+                        name,
+                        event.value.curve.interpolate(from, to, progress),
+                    ),
+                    event.position + n,
+                    tick,
+                );
+            }
+        }
+
+        self.sort();
+    }
+
+    /// Replace each scheduled degree with the one returned by `f`.
+    pub fn map_degrees<F>(&mut self, f: F)
+    where
+        F: Fn(&Degree) -> Degree,
+    {
+        for event in self.degree.events.iter_mut() {
+            event.value = f(&event.value);
+        }
+        for event in self.glide.events.iter_mut() {
+            event.value.from = f(&event.value.from);
+            event.value.to = f(&event.value.to);
+        }
+        self.reset();
     }
 
     #[allow(clippy::type_complexity)]
@@ -120,8 +536,12 @@ impl Pattern {
         let mut modulation = self.modulation.next();
 
         if degree.is_none() && modulation.is_none() {
-            // return the rest of the beat
-            if !self.is_finished && self.cursor.position.tick() != 0 {
+            // return the rest of the beat, unless the pattern stops at
+            // its loop end
+            if !self.is_finished
+                && self.degree.loop_points.is_none()
+                && self.cursor.position.tick() != 0
+            {
                 return Some((Vec::new(), Vec::new()));
             }
 
@@ -163,56 +583,321 @@ impl Pattern {
     fn next_pitches(
         &mut self,
         degree: Vec<Event<Degree>>,
+        glides: Vec<Event<Glide>>,
     ) -> Vec<Event<Value>> {
         let roots = Pattern::values_or_default(self.root.next());
         let octaves = Pattern::values_or_default(self.octave.next());
-        let scales = Pattern::values_or_default(self.scale.next());
+        let scales = if self.scale.is_empty() {
+            vec![Event::new(
+                self.default_scale.clone(),
+                self.cursor.position,
+                EventState::On,
+            )]
+        } else {
+            Pattern::values_or_default(self.scale.next())
+        };
+
+        let tuning = self.tuning().clone();
+        let velocity = self.velocity();
+        let groove = self.groove().cloned();
+        let bends =
+            self.next_bends(glides, &roots[0].value, &scales[0].value, &tuning);
 
         degree
             .iter()
             .enumerate()
             .map(|(n, d)| {
+                // the notes moved earlier by the groove take the values
+                // at their grid, which aren't reached yet
+                let ahead;
+                let (roots, octaves, scales) = match (-d.offset).max(0) {
+                    0 => (&roots, &octaves, &scales),
+                    ticks => {
+                        ahead = self.values_ahead(ticks as u64, &scales);
+                        (&ahead.0, &ahead.1, &ahead.2)
+                    }
+                };
+                // the values are paired with the notes from the same grid
+                let n =
+                    degree[..n].iter().filter(|e| e.offset == d.offset).count();
+                // the groove scales the velocities of the notes at its steps
+                let scale = groove
+                    .as_ref()
+                    .map(|groove| groove.at(d.grid().0).1)
+                    .unwrap_or(1.0);
                 let value = Value::new_pitch(
                     &d.value,
                     &roots[n % roots.len()].value,
                     &octaves[n % octaves.len()].value,
                     &scales[n % scales.len()].value,
+                    &tuning,
+                    d.value.velocity(velocity) * scale,
                 );
                 Event::new(value, self.cursor.position, d.state)
+                    .with_duration(d.duration)
             })
+            .chain(bends)
             .collect()
     }
 
+    // The roots, the octaves and the scales `ticks` after the current
+    // ones. The scales are the `default` ones, if there are no scales.
+    #[allow(clippy::type_complexity)]
+    fn values_ahead(
+        &self,
+        ticks: u64,
+        default: &[Event<Scale>],
+    ) -> (Vec<Event<Root>>, Vec<Event<Octave>>, Vec<Event<Scale>>) {
+        let scales = if self.scale.is_empty() {
+            default.to_vec()
+        } else {
+            Pattern::values_or_default(self.scale.ahead(ticks))
+        };
+        (
+            Pattern::values_or_default(self.root.ahead(ticks)),
+            Pattern::values_or_default(self.octave.ahead(ticks)),
+            scales,
+        )
+    }
+
+    // The bends of the sounding glides relative to their start pitches.
+    fn next_bends(
+        &mut self,
+        glides: Vec<Event<Glide>>,
+        root: &Root,
+        scale: &Scale,
+        tuning: &Tuning,
+    ) -> Vec<Event<Value>> {
+        for event in glides.into_iter() {
+            if event.state == EventState::On {
+                let length = event.duration.as_ticks().max(1);
+                self.gliding.push((event.value, 0, length));
+            }
+        }
+
+        let position = self.cursor.position;
+        let result = self
+            .gliding
+            .iter_mut()
+            .map(|(glide, passed, length)| {
+                let progress = if *length > 1 {
+                    *passed as f64 / (*length - 1) as f64
+                } else {
+                    1.0
+                };
+                *passed += 1;
+                Event::new(
+                    Value::Bend(glide.bend(root, scale, tuning, progress)),
+                    position,
+                    EventState::On,
+                )
+            })
+            .collect();
+        self.gliding.retain(|(_, passed, length)| passed < length);
+
+        result
+    }
+
     fn init_next_values(
         &mut self,
         degree: Vec<Event<Degree>>,
         modulation: Vec<Event<Modulation>>,
+        glides: Vec<Event<Glide>>,
     ) -> Vec<Event<Value>> {
-        let pitches = self.next_pitches(degree).into_iter();
+        let pitches = self.next_pitches(degree, glides).into_iter();
         modulation
             .into_iter()
             .map(|m| {
                 Event::new(Value::from(m.value), self.cursor.position, m.state)
+                    .with_duration(m.duration)
             })
             .chain(pitches)
             .collect()
     }
 
-    impl_schedule_method!(schedule_degree, degree, Degree);
+    impl_schedule_method!(schedule_degree, degree, Degree, true);
+    impl_schedule_method!(schedule_glide, glide, Glide, true);
     impl_schedule_method!(schedule_scale, scale, Scale);
     impl_schedule_method!(schedule_root, root, Root);
     impl_schedule_method!(schedule_octave, octave, Octave);
-    impl_schedule_method!(schedule_modulation, modulation, Modulation);
+    impl_schedule_method!(schedule_modulation, modulation, Modulation, true);
+}
+
+impl HasProperties for Pattern {
+    fn property(&self, key: &Identifier) -> Option<super::Value> {
+        use super::Value::{Boolean, Number, String};
+
+        match key.0.as_str() {
+            "loop" => Some(Boolean(self.is_loop)),
+            "scale" => Some(String(self.scale().key().to_string())),
+            "tuning" => Some(String(self.tuning().name.clone())),
+            "groove" => Some(
+                self.groove()
+                    .map(super::Value::from)
+                    .unwrap_or(super::Value::Nothing),
+            ),
+            "root" => Some(Number(self.root().0 as f64)),
+            "octave" => Some(Number(self.octave().octave as f64)),
+            "velocity" => Some(Number(self.velocity())),
+            "length" => Some(Number(self.length().as_f64())),
+            "loop_start" => Some(Number(self.loop_points().0.as_f64())),
+            "loop_end" => Some(Number(self.loop_points().1.as_f64())),
+            _ => None,
+        }
+    }
+
+    fn set_property(
+        &mut self,
+        key: &Identifier,
+        value: super::Value,
+    ) -> PremitiveResult<()> {
+        let error = |cause: String| PrimitiveError::SetProperty {
+            property_name: key.0.clone(),
+            assignee_name: "<Pattern>".to_string(),
+            cause,
+        };
+        let number = |value: super::Value| match value {
+            super::Value::Number(number)
+                if number.fract() == 0.0 && number >= 0.0 =>
+            {
+                Ok(number as u64)
+            }
+            super::Value::Number(number) => {
+                Err(error(format!("{} is not a non-negative integer", number)))
+            }
+            value => Err(error(format!(
+                "expected <Number>, found {}",
+                value.type_id()
+            ))),
+        };
+
+        match key.0.as_str() {
+            "loop" => match value {
+                super::Value::Boolean(is_loop) => self.set_loop(is_loop),
+                value => {
+                    return Err(error(format!(
+                        "expected <Boolean>, found {}",
+                        value.type_id()
+                    )))
+                }
+            },
+            "root" => self.set_root(Root(number(value)?)),
+            "octave" => self.set_octave(number(value)?),
+            "velocity" => self.set_velocity(velocity(value).map_err(error)?),
+            "length" | "loop_start" | "loop_end" => {
+                let point =
+                    position(value, self.resolution()).map_err(error)?;
+                let (mut start, mut end, mut length) =
+                    (self.loop_start, self.loop_end, self.length);
+                match key.0.as_str() {
+                    "length" => length = point,
+                    "loop_start" => start = point,
+                    _ => end = point,
+                }
+
+                if end.or(length).is_some_and(|end| end.as_ticks() == 0) {
+                    return Err(error("the end should be after zero".into()));
+                }
+                if let (Some(start), Some(end)) = (start, end.or(length)) {
+                    if start >= end {
+                        return Err(error(
+                            "the loop start should be before the end".into(),
+                        ));
+                    }
+                }
+
+                self.length = length;
+                self.loop_start = start;
+                self.loop_end = end;
+                self.update_loop_points();
+            }
+            _ => return Err(error("unknown property".into())),
+        }
+
+        Ok(())
+    }
+}
+
+// A velocity is a number from 0 to 1, `<Nothing>` resets it
+pub(super) fn velocity(value: super::Value) -> Result<Option<f64>, String> {
+    match value {
+        super::Value::Number(velocity) if (0.0..=1.0).contains(&velocity) => {
+            Ok(Some(velocity))
+        }
+        super::Value::Number(velocity) => {
+            Err(format!("{} is out of range 0..1", velocity))
+        }
+        super::Value::Nothing => Ok(None),
+        value => Err(format!(
+            "expected <Number> or <Nothing>, found {}",
+            value.type_id()
+        )),
+    }
+}
+
+// A position is a number of beats or `{beats: 1, ticks: 120}`, `<Nothing>`
+// resets it
+fn position(
+    value: super::Value,
+    resolution: Resolution,
+) -> Result<Option<CursorPosition>, String> {
+    let ticks = |beats: f64| {
+        CursorPosition::from_ticks(
+            (beats * resolution as f64).round() as u64,
+            resolution,
+        )
+    };
+
+    match value {
+        super::Value::Number(beats) if beats >= 0.0 && beats.is_finite() => {
+            Ok(Some(ticks(beats)))
+        }
+        super::Value::Properties(properties) => {
+            let mut result = CursorPosition::new(resolution);
+            for (key, value) in properties.0.into_iter() {
+                let number = match value {
+                    super::Value::Number(number)
+                        if number >= 0.0 && number.fract() == 0.0 =>
+                    {
+                        number
+                    }
+                    value => {
+                        return Err(format!(
+                            "{} of {} is not a non-negative integer",
+                            key.0, value
+                        ))
+                    }
+                };
+                match key.0.as_str() {
+                    "beats" => result += ticks(number),
+                    "ticks" => result += number as u64,
+                    key => return Err(format!("unknown property {}", key)),
+                }
+            }
+            Ok(Some(result))
+        }
+        super::Value::Nothing => Ok(None),
+        value => Err(format!(
+            "expected <Number>, <Properties> or <Nothing>, found {}",
+            value.type_id()
+        )),
+    }
 }
 
 impl Iterator for Pattern {
     type Item = Vec<Event<Value>>;
 
     fn next(&mut self) -> Option<Self::Item> {
+        if self.degree.is_at_start() {
+            self.glide.restart();
+            self.gliding.clear();
+        }
+        let glides = self.glide.next().unwrap_or_default();
+
         let result =
             self.next_degree_and_modulation()
                 .map(|(degree, modulation)| {
-                    self.init_next_values(degree, modulation)
+                    self.init_next_values(degree, modulation, glides)
                 });
 
         self.cursor.next();
@@ -235,6 +920,9 @@ pub struct EventStream<T: Clone + Debug + Default> {
     pub fill_gaps: bool,
     gap_value: Vec<Event<T>>,
     is_sorted: bool,
+    // the stream jumps from the end to the start when it loops, without
+    // them it loops when the events run out
+    loop_points: Option<(CursorPosition, CursorPosition)>,
 }
 
 impl<T: Clone + Debug + Default> Iterator for EventStream<T> {
@@ -243,12 +931,11 @@ impl<T: Clone + Debug + Default> Iterator for EventStream<T> {
     fn next(&mut self) -> Option<Self::Item> {
         self.sort();
 
-        if self.events.is_empty()
-            || (self.increment >= self.events.len() && !self.is_loop)
-        {
+        if self.events.is_empty() || self.is_over() {
             return None;
         }
 
+        let end = self.loop_points.map(|(_, end)| end);
         let mut result: Self::Item = Vec::new();
         for event in self.events[self.increment..].iter() {
             if event.position == self.cursor.position {
@@ -260,6 +947,9 @@ impl<T: Clone + Debug + Default> Iterator for EventStream<T> {
                 break;
             }
         }
+        if let Some(end) = end {
+            self.cut_at(end, &mut result);
+        }
         self.handle_gaps(&mut result);
 
         self.cursor.next().unwrap(); // Cursor::next is always Some
@@ -282,7 +972,7 @@ impl<T: Clone + Debug + Default> EventStream<T> {
 
     fn sort(&mut self) {
         if !self.is_sorted {
-            self.events.sort_by(|a, b| a.position.cmp(&b.position));
+            self.events.sort_by_key(|e| e.position);
             self.is_sorted = true;
         }
     }
@@ -293,6 +983,117 @@ impl<T: Clone + Debug + Default> EventStream<T> {
         self.is_sorted = false;
     }
 
+    // the value of the first event
+    fn first(&self) -> Option<&T> {
+        self.events
+            .iter()
+            .filter(|e| e.state == EventState::On)
+            .min_by_key(|e| e.position)
+            .map(|e| &e.value)
+    }
+
+    /// Add all events of `other` to the stream.
+    pub fn append(&mut self, mut other: EventStream<T>) {
+        self.events.append(&mut other.events);
+        self.is_sorted = false;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    /// Check if the next value is the first one of the loop.
+    pub fn is_at_start(&self) -> bool {
+        match self.loop_points {
+            Some((start, _)) => self.cursor.position == start,
+            None => self.increment == 0 && self.cursor.position.as_ticks() == 0,
+        }
+    }
+
+    /// Loop from `start` to `end` instead of looping when the events run
+    /// out. The events after the end are skipped and the values sounding
+    /// at the end are stopped. A stream, which doesn't loop, stops at
+    /// the end.
+    pub fn set_loop_points(
+        &mut self,
+        loop_points: Option<(CursorPosition, CursorPosition)>,
+    ) {
+        self.loop_points = loop_points;
+    }
+
+    /// Continue from the start of the loop.
+    pub fn restart(&mut self) {
+        match self.loop_points {
+            Some((start, _)) => self.jump(start),
+            None => self.reset(),
+        }
+    }
+
+    fn clear(&mut self) {
+        self.events.clear();
+        self.reset();
+    }
+
+    /// Get the position right after the last off event.
+    fn end(&self) -> Option<CursorPosition> {
+        self.events
+            .iter()
+            .filter(|e| e.state == EventState::Off)
+            .map(|e| e.position + 1)
+            .max()
+    }
+
+    /// Get the on events, i.e. the events, which start values.
+    fn starts(&self) -> Vec<Event<T>> {
+        self.events
+            .iter()
+            .filter(|e| e.state == EventState::On)
+            .cloned()
+            .collect()
+    }
+
+    fn map_timing<F>(&mut self, f: &F)
+    where
+        F: Fn(CursorPosition, Duration) -> Vec<(CursorPosition, Duration)>,
+    {
+        let starts = self.starts();
+        self.events.clear();
+        for start in starts.into_iter() {
+            let (grid, length) = start.grid();
+            for (position, duration) in f(grid, length) {
+                self.events.push(
+                    Event::new(start.value.clone(), position, EventState::On)
+                        .with_duration(duration),
+                );
+                self.events.push(Event::new(
+                    start.value.clone(),
+                    (position + duration) - 1,
+                    EventState::Off,
+                ));
+            }
+        }
+        self.is_sorted = false;
+        self.reset();
+    }
+
+    fn apply_groove(&mut self, groove: Option<&Groove>) {
+        for event in self.events.iter_mut() {
+            if event.state == EventState::On {
+                event.apply_groove(groove);
+            }
+        }
+        self.is_sorted = false;
+    }
+
+    // The values `ticks` after the ones returned by the last `next`.
+    fn ahead(&self, ticks: u64) -> Option<Vec<Event<T>>> {
+        let mut stream = self.clone();
+        for _ in 1..ticks {
+            stream.next();
+        }
+        stream.next()
+    }
+
     /// Get [CursorPosition](../clock/struct.CursorPosition.html) of the last event.
     pub fn last_position(&self) -> Option<CursorPosition> {
         self.events.last().map(|e| e.position)
@@ -313,9 +1114,71 @@ impl<T: Clone + Debug + Default> EventStream<T> {
         self.cursor.reset();
     }
 
+    fn is_over(&self) -> bool {
+        match self.loop_points {
+            _ if self.is_loop => false,
+            Some((_, end)) => self.cursor.position >= end,
+            None => self.increment >= self.events.len(),
+        }
+    }
+
     fn check_loop(&mut self) {
-        if self.is_loop && self.increment >= self.events.len() {
-            self.reset();
+        if !self.is_loop {
+            return;
+        }
+
+        match self.loop_points {
+            Some((start, end)) if self.cursor.position >= end => {
+                self.jump(start)
+            }
+            None if self.increment >= self.events.len() => self.reset(),
+            _ => (),
+        }
+    }
+
+    // Move the cursor to the `position` skipping the events before it.
+    // The value at the position is the one started last before it.
+    fn jump(&mut self, position: CursorPosition) {
+        self.sort();
+        self.cursor.position = position;
+        self.increment = self
+            .events
+            .iter()
+            .take_while(|e| e.position < position)
+            .count();
+        if let Some(event) = self.events[..self.increment]
+            .iter()
+            .rev()
+            .find(|e| e.state == EventState::On)
+        {
+            self.gap_value = vec![event.clone()];
+        }
+    }
+
+    // Shorten the values started at the cursor to the `end` and stop
+    // the ones, which are sounding at the last tick before it.
+    fn cut_at(&self, end: CursorPosition, events: &mut Vec<Event<T>>) {
+        let position = self.cursor.position;
+        for event in events.iter_mut() {
+            if event.state == EventState::On && position + event.duration > end
+            {
+                event.duration = end - position;
+            }
+        }
+
+        if position + 1 == end {
+            events.extend(
+                self.events
+                    .iter()
+                    .filter(|e| {
+                        e.state == EventState::On
+                            && e.position < end
+                            && e.position + e.duration > end
+                    })
+                    .map(|e| {
+                        Event::new(e.value.clone(), position, EventState::Off)
+                    }),
+            );
         }
     }
 
@@ -346,6 +1209,10 @@ pub struct Event<V: Clone + Debug + Default> {
     value: V,
     position: CursorPosition,
     state: EventState,
+    duration: Duration,
+    // the shift of the start by the groove in ticks, the end stays on
+    // the grid
+    offset: i64,
 }
 
 impl<T: Clone + Debug + Default> Event<T> {
@@ -354,48 +1221,105 @@ impl<T: Clone + Debug + Default> Event<T> {
             value,
             position,
             state,
+            duration: Duration::new(position.resolution()),
+            offset: 0,
         }
     }
+
+    /// Set the length of the value started by the event.
+    pub fn with_duration(mut self, duration: Duration) -> Self {
+        self.duration = duration;
+        self
+    }
+
+    pub fn value(&self) -> &T {
+        &self.value
+    }
+
+    pub fn position(&self) -> CursorPosition {
+        self.position
+    }
+
+    pub fn state(&self) -> EventState {
+        self.state
+    }
+
+    /// The length of the value started by the event. It's zero for
+    /// the off events.
+    pub fn duration(&self) -> Duration {
+        self.duration
+    }
+
+    // The position and the duration before the groove is applied.
+    fn grid(&self) -> (CursorPosition, Duration) {
+        let resolution = self.position.resolution();
+        let ticks = |ticks: i64| {
+            CursorPosition::from_ticks(ticks.max(0) as u64, resolution)
+        };
+        (
+            ticks(self.position.as_ticks() as i64 - self.offset),
+            ticks(self.duration.as_ticks() as i64 + self.offset),
+        )
+    }
+
+    // Shift the start of the event by the groove's offset. The notes
+    // keep at least a tick and don't start before the zero position.
+    fn apply_groove(&mut self, groove: Option<&Groove>) {
+        let (grid, length) = self.grid();
+        let (start, length) =
+            (grid.as_ticks() as i64, length.as_ticks() as i64);
+        let offset = groove
+            .map(|groove| groove.at(grid).0)
+            .unwrap_or(0)
+            .clamp(-start, (length - 1).max(0));
+        let resolution = grid.resolution();
+
+        self.position =
+            CursorPosition::from_ticks((start + offset) as u64, resolution);
+        self.duration =
+            CursorPosition::from_ticks((length - offset) as u64, resolution);
+        self.offset = offset;
+    }
 }
 
 impl<T: Clone + Debug + Default> From<(T, CursorPosition)> for Event<T> {
     fn from(value: (T, CursorPosition)) -> Self {
-        Event {
-            value: value.0,
-            position: value.1,
-            state: EventState::On,
-        }
+        Event::new(value.0, value.1, EventState::On)
     }
 }
 
-#[derive(Clone, Debug, PartialEq, Copy)]
+#[derive(Clone, Debug, PartialEq, Copy, Default)]
 pub enum EventState {
+    #[default]
     On,
     Off,
 }
 
-impl Default for EventState {
-    fn default() -> Self {
-        Self::On
-    }
-}
-
 #[derive(Clone, Debug, PartialEq)]
 pub enum Value {
-    Pitch(u64),
+    /// MIDI note number, which is fractional for the pitches between
+    /// the 12-EDO ones, and velocity from 0 to 1.
+    Pitch(f64, f64),
     Modulation(String, f64),
+    /// Pitch bend in semitones of the notes of the slot.
+    Bend(f64),
 }
 
 impl Value {
+    /// The root, the scale's pitch set and the alteration are in the steps
+    /// of the `tuning`.
     pub fn new_pitch(
         degree: &Degree,
         root: &Root,
         octave: &Octave,
         scale: &Scale,
+        tuning: &Tuning,
+        velocity: f64,
     ) -> Value {
-        let pitch_offset = degree.as_pitch_at_scale(scale);
-        let pitch = ((octave.pitch + root.0) as i64 + pitch_offset).max(0);
-        Value::Pitch(pitch as u64)
+        let steps = tuning.steps();
+        let step = (octave.octave * steps + root.0) as i64
+            + degree.as_step_at_scale(scale, steps);
+        Value::Pitch(tuning.pitch(step).max(0.0), velocity.clamp(0.0, 1.0))
     }
 }
 
@@ -407,31 +1331,32 @@ impl From<Modulation> for Value {
 
 impl Default for Value {
     fn default() -> Self {
-        Value::Pitch(60)
+        Value::Pitch(60.0, DEFAULT_VELOCITY)
     }
 }
 
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Default)]
 pub struct Degree {
     pub value: u64,
     pub alteration: i64,
+    /// The number of accents, it's negative for ghost notes.
+    pub accent: i64,
 }
 
 impl Degree {
-    pub fn as_pitch_at_scale(&self, scale: &Scale) -> i64 {
-        let octave_offset = self.value / scale.pitch_set.len() as u64 * 12;
+    /// Get the step of the degree, where `steps` is the number of steps
+    /// in the tuning's period.
+    pub fn as_step_at_scale(&self, scale: &Scale, steps: u64) -> i64 {
+        let octave_offset = self.value / scale.pitch_set.len() as u64 * steps;
         (scale.pitch_set[self.value as usize % scale.pitch_set.len()]
             + octave_offset) as i64
             + self.alteration
     }
-}
 
-impl Default for Degree {
-    fn default() -> Self {
-        Degree {
-            value: 0,
-            alteration: 0,
-        }
+    /// Get the velocity of the degree relative to the `velocity` of
+    /// the pattern.
+    pub fn velocity(&self, velocity: f64) -> f64 {
+        (velocity + self.accent as f64 * ACCENT).clamp(0.0, 1.0)
     }
 }
 
@@ -440,6 +1365,7 @@ impl From<u64> for Degree {
         Self {
             value,
             alteration: 0,
+            accent: 0,
         }
     }
 }
@@ -503,6 +1429,81 @@ impl Default for Octave {
     }
 }
 
+/// A glide from one note to another, which sounds as a bend of the first
+/// note.
+#[derive(Clone, Debug, PartialEq, Default)]
+pub struct Glide {
+    pub from: Degree,
+    pub to: Degree,
+    /// The octave of `to` relative to the one of `from`.
+    pub octaves: i64,
+    pub curve: Curve,
+}
+
+impl Glide {
+    /// Get the bend in semitones at `progress` from 0 to 1.
+    pub fn bend(
+        &self,
+        root: &Root,
+        scale: &Scale,
+        tuning: &Tuning,
+        progress: f64,
+    ) -> f64 {
+        let octave = Octave::default();
+        let target = Octave::with_octave(
+            (octave.get_octave_number() as i64 + self.octaves).max(0) as u64,
+        );
+        let pitch = |degree: &Degree, octave: &Octave| match Value::new_pitch(
+            degree, root, octave, scale, tuning, 0.0,
+        ) {
+            Value::Pitch(pitch, _) => pitch,
+            _ => unreachable!(),
+        };
+
+        let from = pitch(&self.from, &octave);
+        let to = pitch(&self.to, &target);
+        self.curve.interpolate(from, to, progress) - from
+    }
+}
+
+/// The shape of transitions between values.
+#[derive(Clone, Copy, Debug, PartialEq, Default)]
+pub enum Curve {
+    #[default]
+    Linear,
+    /// Each step multiplies the value by the same factor. It falls back
+    /// to the linear curve if the values have different signs or one of
+    /// them is zero.
+    Exponential,
+}
+
+impl Curve {
+    /// Get the value between `from` and `to` at `progress` from 0 to 1.
+    pub fn interpolate(self, from: f64, to: f64, progress: f64) -> f64 {
+        match self {
+            Curve::Exponential if from * to > 0.0 => {
+                from * (to / from).powf(progress)
+            }
+            _ => from + (to - from) * progress,
+        }
+    }
+}
+
+impl FromStr for Curve {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "linear" => Ok(Curve::Linear),
+            "exponential" => Ok(Curve::Exponential),
+            _ => Err(format!(
+                "expected \"linear\" or \"exponential\", found {:?}",
+                name
+            )),
+        }
+    }
+}
+
 #[derive(Clone, Debug, PartialEq, Default)]
 pub struct Modulation {
     name: String,
@@ -519,9 +1520,33 @@ impl Modulation {
 }
 
 #[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(try_from = "ScaleDefinition")]
 pub struct Scale {
     pub name: String,
     pub pitch_set: Vec<u64>,
+    /// The name the scale is looked up by, i.e. `major`.
+    pub key: Option<String>,
+}
+
+// The scale as it's defined in the settings.
+#[derive(Deserialize)]
+struct ScaleDefinition {
+    name: String,
+    pitch_set: Vec<u64>,
+}
+
+impl TryFrom<ScaleDefinition> for Scale {
+    type Error = String;
+
+    fn try_from(definition: ScaleDefinition) -> Result<Self, Self::Error> {
+        if definition.pitch_set.is_empty() {
+            return Err(format!(
+                "the pitch set of scale {} is empty",
+                definition.name
+            ));
+        }
+        Ok(Scale::new(&definition.name, &definition.pitch_set))
+    }
 }
 
 impl Scale {
@@ -529,8 +1554,38 @@ impl Scale {
         Scale {
             name: name.to_string(),
             pitch_set: pitch_set.to_vec(),
+            key: None,
         }
     }
+
+    /// The name to look the scale up by, it's the display name if
+    /// the scale isn't in a catalogue.
+    pub fn key(&self) -> &str {
+        self.key.as_deref().unwrap_or(&self.name)
+    }
+
+    /// Get the built-in scales by their names, i.e. `major`, `dorian`,
+    /// `minor_pentatonic`, etc.
+    pub fn catalogue() -> HashMap<String, Scale> {
+        let mut config = config::Config::new();
+        // the file is a part of the crate, so it's always valid
+        config
+            .merge(config::File::from_str(SCALES, config::FileFormat::Toml))
+            .unwrap();
+        let scales: HashMap<String, Scale> = config.try_into().unwrap();
+        Scale::with_keys(scales)
+    }
+
+    /// Set the keys of the `scales` to their names in the map.
+    pub fn with_keys(scales: HashMap<String, Scale>) -> HashMap<String, Scale> {
+        scales
+            .into_iter()
+            .map(|(key, mut scale)| {
+                scale.key = Some(key.clone());
+                (key, scale)
+            })
+            .collect()
+    }
 }
 
 impl Default for Scale {
@@ -538,6 +1593,7 @@ impl Default for Scale {
         Scale {
             name: DEFAULT_SCALE_NAME.to_string(),
             pitch_set: DEFAULT_PITCH_SET.to_vec().clone(),
+            key: Some(DEFAULT_SCALE_KEY.to_string()),
         }
     }
 }
@@ -715,14 +1771,14 @@ mod tests {
     fn degree_as_pitch() {
         let scale = Scale::default();
         let degree = Degree::default();
-        assert_eq!(0, degree.as_pitch_at_scale(&scale));
+        assert_eq!(0, degree.as_step_at_scale(&scale, 12));
 
         let degree = Degree::from(13);
-        assert_eq!(13, degree.as_pitch_at_scale(&scale));
+        assert_eq!(13, degree.as_step_at_scale(&scale, 12));
 
         let mut degree = Degree::from(0);
         degree.alteration = -4;
-        assert_eq!(-4, degree.as_pitch_at_scale(&scale));
+        assert_eq!(-4, degree.as_step_at_scale(&scale, 12));
     }
 
     #[test]
@@ -731,22 +1787,40 @@ mod tests {
         let mut root = Root::default();
         let mut octave = Octave::default();
         let scale = Scale::default();
-        let value = Value::new_pitch(&degree, &root, &octave, &scale);
+        let tuning = Tuning::default();
+        let value =
+            Value::new_pitch(&degree, &root, &octave, &scale, &tuning, 0.5);
 
-        assert_eq!(Value::Pitch(60), value);
+        assert_eq!(Value::Pitch(60.0, 0.5), value);
 
         octave.set_as_octave(0);
         root.0 = 0;
         degree.alteration = -5;
-        let value = Value::new_pitch(&degree, &root, &octave, &scale);
-        assert_eq!(Value::Pitch(0), value);
+        let value =
+            Value::new_pitch(&degree, &root, &octave, &scale, &tuning, 0.5);
+        assert_eq!(Value::Pitch(0.0, 0.5), value);
 
         octave.set_as_octave(4);
         root.0 = 2;
         degree.value = 3;
         degree.alteration = 1;
-        let value = Value::new_pitch(&degree, &root, &octave, &scale);
-        assert_eq!(Value::Pitch(54), value);
+        let value =
+            Value::new_pitch(&degree, &root, &octave, &scale, &tuning, 0.5);
+        assert_eq!(Value::Pitch(54.0, 0.5), value);
+    }
+
+    #[test]
+    fn scale_catalogue() {
+        let scales = Scale::catalogue();
+
+        assert_eq!(
+            Some(&vec![0, 2, 3, 5, 7, 9, 10]),
+            scales.get("dorian").map(|scale| &scale.pitch_set)
+        );
+        assert_eq!(
+            Some(&Scale::default().pitch_set),
+            scales.get("chromatic").map(|scale| &scale.pitch_set)
+        );
     }
 
     #[test]
@@ -763,16 +1837,17 @@ mod tests {
 
         assert_eq!(
             vec![
-                Event {
-                    value: Value::Pitch(61),
-                    position: (0, 1, resolution).into(),
-                    state: EventState::On,
-                },
-                Event {
-                    value: Value::Pitch(61),
-                    position: (0, 1, resolution).into(),
-                    state: EventState::Off,
-                },
+                Event::new(
+                    Value::Pitch(61.0, DEFAULT_VELOCITY),
+                    (0, 1, resolution).into(),
+                    EventState::On,
+                )
+                .with_duration((0, 1, resolution).into()),
+                Event::new(
+                    Value::Pitch(61.0, DEFAULT_VELOCITY),
+                    (0, 1, resolution).into(),
+                    EventState::Off,
+                ),
             ],
             pattern.next().unwrap()
         );
@@ -827,15 +1902,17 @@ mod tests {
                     Value::Modulation("v".to_string(), 0.1),
                     (0, 0, resolution).into(),
                     EventState::On,
-                ),
+                )
+                .with_duration((0, 3, resolution).into()),
                 Event::new(
-                    Value::Pitch(60),
+                    Value::Pitch(60.0, DEFAULT_VELOCITY),
                     (0, 0, resolution).into(),
                     EventState::On,
-                ),
+                )
+                .with_duration((0, 2, resolution).into()),
             ],
             vec![Event::new(
-                Value::Pitch(60),
+                Value::Pitch(60.0, DEFAULT_VELOCITY),
                 (0, 1, resolution).into(),
                 EventState::Off,
             )],
@@ -852,12 +1929,14 @@ mod tests {
                     Value::Modulation("v".to_string(), 0.2),
                     (1, 0, resolution).into(),
                     EventState::On,
-                ),
+                )
+                .with_duration((0, 3, resolution).into()),
                 Event::new(
-                    Value::Pitch(61),
+                    Value::Pitch(61.0, DEFAULT_VELOCITY),
                     (1, 0, resolution).into(),
                     EventState::On,
-                ),
+                )
+                .with_duration((0, 4, resolution).into()),
             ],
             vec![],
             vec![Event::new(
@@ -866,7 +1945,7 @@ mod tests {
                 EventState::Off,
             )],
             vec![Event::new(
-                Value::Pitch(61),
+                Value::Pitch(61.0, DEFAULT_VELOCITY),
                 (1, 3, resolution).into(),
                 EventState::Off,
             )],
@@ -877,15 +1956,17 @@ mod tests {
                     Value::Modulation("v".to_string(), 0.3),
                     (2, 0, resolution).into(),
                     EventState::On,
-                ),
+                )
+                .with_duration((0, 3, resolution).into()),
                 Event::new(
-                    Value::Pitch(60),
+                    Value::Pitch(60.0, DEFAULT_VELOCITY),
                     (2, 0, resolution).into(),
                     EventState::On,
-                ),
+                )
+                .with_duration((0, 2, resolution).into()),
             ],
             vec![Event::new(
-                Value::Pitch(60),
+                Value::Pitch(60.0, DEFAULT_VELOCITY),
                 (2, 1, resolution).into(),
                 EventState::Off,
             )],
@@ -902,12 +1983,14 @@ mod tests {
                     Value::Modulation("v".to_string(), 0.4),
                     (3, 0, resolution).into(),
                     EventState::On,
-                ),
+                )
+                .with_duration((0, 3, resolution).into()),
                 Event::new(
-                    Value::Pitch(61),
+                    Value::Pitch(61.0, DEFAULT_VELOCITY),
                     (3, 0, resolution).into(),
                     EventState::On,
-                ),
+                )
+                .with_duration((0, 4, resolution).into()),
             ],
             vec![],
             vec![Event::new(
@@ -916,7 +1999,7 @@ mod tests {
                 EventState::Off,
             )],
             vec![Event::new(
-                Value::Pitch(61),
+                Value::Pitch(61.0, DEFAULT_VELOCITY),
                 (3, 3, resolution).into(),
                 EventState::Off,
             )],
@@ -933,4 +2016,132 @@ mod tests {
             assert_eq!(None, pattern.next());
         }
     }
+
+    #[test]
+    fn pattern_loop_points() {
+        let resolution = 1;
+        let position = |beat| CursorPosition::from_ticks(beat, resolution);
+        let mut pattern = Pattern::new(Cursor::new(resolution));
+        pattern.schedule_degree(0.into(), position(0), position(1));
+        pattern.schedule_degree(1.into(), position(1), position(3));
+        pattern.set_loop(true);
+        pattern.set_length(Some(position(3)));
+        pattern.set_loop_start(Some(position(1)));
+        assert_eq!((position(1), position(3)), pattern.loop_points());
+
+        // the pitches with the states and the durations in beats
+        let events: Vec<Vec<(f64, EventState, u64)>> = pattern
+            .by_ref()
+            .take(6)
+            .map(|events| {
+                events
+                    .into_iter()
+                    .filter_map(|e| match e.value() {
+                        Value::Pitch(pitch, _) => {
+                            Some((*pitch, e.state(), e.duration().as_ticks()))
+                        }
+                        _ => None,
+                    })
+                    .collect()
+            })
+            .collect();
+        let on = |pitch, duration| (pitch, EventState::On, duration);
+        let off = |pitch| (pitch, EventState::Off, 0);
+        assert_eq!(
+            vec![
+                vec![on(60.0, 1), off(60.0)],
+                vec![on(61.0, 2)],
+                vec![off(61.0)],
+                vec![on(61.0, 2)],
+                vec![off(61.0)],
+                vec![on(61.0, 2)],
+            ],
+            events
+        );
+
+        // the pattern stops at the end, if it doesn't loop
+        pattern.set_loop(false);
+        pattern.set_length(Some(position(5)));
+        pattern.set_loop_start(None);
+        pattern.reset();
+        assert_eq!(5, pattern.count());
+    }
+
+    #[test]
+    fn groove_keeps_values_on_grid() {
+        let resolution = 4;
+        let beat = |beat| CursorPosition::from_ticks(beat * resolution, 4);
+        let groove = Groove::new("early", 1, &[0, -1], &[]).unwrap();
+
+        for groove_first in &[true, false] {
+            let mut pattern = Pattern::new(Cursor::new(resolution));
+            if *groove_first {
+                pattern.set_groove(Some(groove.clone()));
+            }
+            // a note at the first beat, a chord with two octaves at the
+            // second one and a note at the third one
+            for (n, octaves) in
+                [vec![4], vec![6, 2], vec![3]].iter().enumerate()
+            {
+                for octave in octaves {
+                    pattern.schedule_degree(0.into(), beat(n as u64), beat(1));
+                    pattern.schedule_octave(
+                        Octave::with_octave(*octave),
+                        beat(n as u64),
+                        beat(1),
+                    );
+                }
+            }
+            if !*groove_first {
+                pattern.set_groove(Some(groove.clone()));
+            }
+
+            let pitches: Vec<(u64, f64)> = pattern
+                .flatten()
+                .filter(|e| e.state() == EventState::On)
+                .filter_map(|e| match e.value() {
+                    Value::Pitch(pitch, _) => {
+                        Some((e.position().as_ticks(), *pitch))
+                    }
+                    _ => None,
+                })
+                .collect();
+            assert_eq!(
+                vec![(0, 48.0), (3, 72.0), (3, 24.0), (8, 36.0)],
+                pitches,
+                "groove first: {}",
+                groove_first
+            );
+        }
+    }
+
+    #[test]
+    fn polymeter() {
+        let resolution = 2;
+        let pattern = |length| {
+            let mut pattern = Pattern::new(Cursor::new(resolution));
+            pattern.schedule_degree(
+                0.into(),
+                CursorPosition::new(resolution),
+                (1, 0, resolution).into(),
+            );
+            pattern.set_length(Some((length, 0, resolution).into()));
+            pattern.set_loop(true);
+            pattern
+        };
+        // the beats of the note ons
+        let starts = |pattern: Pattern| -> Vec<u64> {
+            pattern
+                .take(20 * resolution as usize)
+                .enumerate()
+                .filter(|(_, events)| {
+                    events.iter().any(|e| e.state() == EventState::On)
+                })
+                .map(|(tick, _)| tick as u64 / resolution)
+                .collect()
+        };
+
+        assert_eq!(vec![0, 5, 10, 15], starts(pattern(5)));
+        assert_eq!(vec![0, 4, 8, 12, 16], starts(pattern(4)));
+    }
 }
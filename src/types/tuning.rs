@@ -0,0 +1,222 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+const CENTS_PER_SEMITONE: f64 = 100.0;
+const EDO_SUFFIX: &str = "edo";
+
+type TuningResult<T> = Result<T, TuningError>;
+
+/// Tuning maps steps to pitches. Pitches are MIDI note numbers, which
+/// can be fractional, i.e. `60.5` is a quarter tone above the middle C.
+///
+/// As in the [Scala](http://www.huygens-fokker.org/scala/scl_format.html)
+/// format, a tuning is a list of intervals in cents from the first step,
+/// where the last interval is the period (usually the octave). The step
+/// `0` is the MIDI pitch `0`, so the steps `n * steps()` are the C's
+/// as long as the period is the octave.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Tuning {
+    pub name: String,
+    cents: Vec<f64>,
+}
+
+impl Tuning {
+    pub fn new(name: &str, cents: &[f64]) -> TuningResult<Self> {
+        match cents.last() {
+            Some(period) if *period > 0.0 => Ok(Tuning {
+                name: name.to_string(),
+                cents: cents.to_vec(),
+            }),
+            _ => Err(TuningError::Parse(format!(
+                "the period of {} should be positive",
+                name
+            ))),
+        }
+    }
+
+    /// Equal division of the octave into `steps` steps.
+    pub fn edo(steps: u64) -> Self {
+        let steps = steps.max(1);
+        Tuning {
+            name: format!("{}{}", steps, EDO_SUFFIX),
+            cents: (1..=steps)
+                .map(|n| n as f64 * 1200.0 / steps as f64)
+                .collect(),
+        }
+    }
+
+    /// Tuning from the frequency ratios of the steps, i.e. `[9/8, 5/4,
+    /// ..., 2]` for just intonation.
+    pub fn from_ratios(name: &str, ratios: &[f64]) -> TuningResult<Self> {
+        let cents: Vec<f64> =
+            ratios.iter().map(|r| ratio_to_cents(*r)).collect();
+        Tuning::new(name, &cents)
+    }
+
+    /// Get an equal division of the octave by its name, i.e. `19edo`.
+    pub fn from_name(name: &str) -> Option<Self> {
+        if !name.ends_with(EDO_SUFFIX) {
+            return None;
+        }
+
+        name[..name.len() - EDO_SUFFIX.len()]
+            .parse()
+            .ok()
+            .filter(|steps| *steps > 0)
+            .map(Tuning::edo)
+    }
+
+    /// Parse the content of a Scala `.scl` file. The description is used
+    /// as the name.
+    pub fn from_scala(source: &str) -> TuningResult<Self> {
+        let mut lines = source
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.starts_with('!'));
+
+        let name = lines.next().unwrap_or_default().to_string();
+        let count: usize = lines
+            .next()
+            .and_then(|line| line.split_whitespace().next())
+            .and_then(|count| count.parse().ok())
+            .ok_or_else(|| {
+                TuningError::Parse("missing number of notes".to_string())
+            })?;
+
+        let cents = lines
+            .filter(|line| !line.is_empty())
+            .take(count)
+            .map(parse_scala_pitch)
+            .collect::<TuningResult<Vec<f64>>>()?;
+
+        if cents.len() != count {
+            return Err(TuningError::Parse(format!(
+                "expected {} notes, found {}",
+                count,
+                cents.len()
+            )));
+        }
+
+        Tuning::new(&name, &cents)
+    }
+
+    /// Load a Scala `.scl` file.
+    pub fn load<P: AsRef<Path>>(path: P) -> TuningResult<Self> {
+        Tuning::from_scala(&fs::read_to_string(path)?)
+    }
+
+    /// The number of steps in the period.
+    pub fn steps(&self) -> u64 {
+        self.cents.len() as u64
+    }
+
+    pub fn pitch(&self, step: i64) -> f64 {
+        let steps = self.cents.len() as i64;
+        let period = self.cents[self.cents.len() - 1];
+        let offset = match step.rem_euclid(steps) {
+            0 => 0.0,
+            n => self.cents[n as usize - 1],
+        };
+
+        (step.div_euclid(steps) as f64 * period + offset) / CENTS_PER_SEMITONE
+    }
+}
+
+impl Default for Tuning {
+    fn default() -> Self {
+        Tuning::edo(12)
+    }
+}
+
+// A pitch is either cents, if it contains a period, or a ratio like `5/4`
+// or `2`. Anything after the value is a comment.
+fn parse_scala_pitch(line: &str) -> TuningResult<f64> {
+    let value = line.split_whitespace().next().unwrap_or_default();
+    let error = || TuningError::Parse(format!("invalid pitch {}", value));
+
+    if value.contains('.') {
+        return value.parse().map_err(|_| error());
+    }
+
+    let mut parts = value.splitn(2, '/');
+    let numerator: f64 = parts
+        .next()
+        .unwrap_or_default()
+        .parse()
+        .map_err(|_| error())?;
+    let denominator: f64 = match parts.next() {
+        Some(denominator) => denominator.parse().map_err(|_| error())?,
+        None => 1.0,
+    };
+
+    if numerator <= 0.0 || denominator <= 0.0 {
+        return Err(error());
+    }
+
+    Ok(ratio_to_cents(numerator / denominator))
+}
+
+fn ratio_to_cents(ratio: f64) -> f64 {
+    1200.0 * ratio.log2()
+}
+
+#[derive(Debug, Fail)]
+pub enum TuningError {
+    #[fail(display = "{}", 0)]
+    Io(io::Error),
+    #[fail(display = "Invalid tuning: {}", 0)]
+    Parse(String),
+}
+
+impl From<io::Error> for TuningError {
+    fn from(error: io::Error) -> Self {
+        TuningError::Io(error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_relative_eq;
+
+    #[test]
+    fn edo_pitches() {
+        let tuning = Tuning::default();
+        assert_relative_eq!(60.0, tuning.pitch(60));
+        assert_relative_eq!(-1.0, tuning.pitch(-1));
+
+        let tuning = Tuning::from_name("19edo").unwrap();
+        assert_eq!(19, tuning.steps());
+        assert_relative_eq!(60.0, tuning.pitch(95));
+        assert_relative_eq!(60.0 + 12.0 / 19.0, tuning.pitch(96));
+
+        assert_eq!(None, Tuning::from_name("0edo"));
+        assert_eq!(None, Tuning::from_name("dorian"));
+    }
+
+    #[test]
+    fn parse_scala() {
+        let source = "! just.scl\n\
+                      !\n\
+                      Just intonation\n \
+                      4\n\
+                      !\n\
+                      9/8\n\
+                      5/4 major third\n\
+                      701.955\n\
+                      2\n";
+        let tuning = Tuning::from_scala(source).unwrap();
+
+        assert_eq!("Just intonation", tuning.name);
+        assert_eq!(4, tuning.steps());
+        assert_relative_eq!(62.039_1, tuning.pitch(21), epsilon = 1e-4);
+        assert_relative_eq!(63.863_137, tuning.pitch(22), epsilon = 1e-6);
+        assert_relative_eq!(67.019_55, tuning.pitch(23), epsilon = 1e-6);
+        assert_relative_eq!(72.0, tuning.pitch(24), epsilon = 1e-6);
+
+        assert!(Tuning::from_scala("Broken\n2\n9/8\n").is_err());
+        assert!(Tuning::from_scala("Broken\n1\n0/1\n").is_err());
+        assert!(Tuning::from_scala("Broken\nmany\n").is_err());
+    }
+}